@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pystr_to_utf8::{qdb_pystr_buf_free, qdb_pystr_buf_new, qdb_ucs1_to_utf8};
+
+fn ucs1_to_utf8(input: &[u8]) -> usize {
+    unsafe {
+        let buf = qdb_pystr_buf_new();
+        let mut size_out = 0usize;
+        let mut buf_out = std::ptr::null();
+        qdb_ucs1_to_utf8(
+            buf,
+            input.len(),
+            input.as_ptr(),
+            &mut size_out,
+            &mut buf_out,
+        );
+        qdb_pystr_buf_free(buf);
+        size_out
+    }
+}
+
+fn bench_ucs1_to_utf8(c: &mut Criterion) {
+    let ascii = "the quick brown fox jumps over the lazy dog ".repeat(100);
+    let mixed_latin1: String = "café mañana naïve façade über ".repeat(100);
+
+    let mut group = c.benchmark_group("qdb_ucs1_to_utf8");
+    group.bench_function("ascii_only", |b| {
+        b.iter(|| ucs1_to_utf8(black_box(ascii.as_bytes())))
+    });
+    group.bench_function("mixed_latin1", |b| {
+        // Py_UCS1 strings are one byte per code point, so re-pack the
+        // Latin-1-representable `mixed_latin1` chars back into bytes rather
+        // than benchmarking its (multi-byte) UTF-8 form.
+        let bytes: Vec<u8> = mixed_latin1.chars().map(|c| c as u32 as u8).collect();
+        b.iter(|| ucs1_to_utf8(black_box(&bytes)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_ucs1_to_utf8);
+criterion_main!(benches);
@@ -23,6 +23,7 @@
  ******************************************************************************/
 
 use std::ffi::c_char;
+use std::mem::MaybeUninit;
 use std::slice::from_raw_parts;
 
 #[allow(non_camel_case_types)]
@@ -103,96 +104,92 @@ fn get_dest(chain: &mut Vec<String>, len: usize) -> &mut String {
     chain.last_mut().unwrap()
 }
 
+/// View the first `len` bytes of `spare`, an uninitialized spare-capacity
+/// slice, as a plain `&mut [u8]` so that APIs like `char::encode_utf8` can
+/// write directly into it. Safety: the caller must fully initialize the
+/// returned slice before the underlying `Vec`'s length is ever advanced over
+/// it, since no uninitialized byte may become reachable as `u8` at a safe
+/// boundary.
+#[inline(always)]
+unsafe fn uninit_prefix_as_mut_slice(spare: &mut [MaybeUninit<u8>], len: usize) -> &mut [u8] {
+    std::slice::from_raw_parts_mut(spare.as_mut_ptr() as *mut u8, len)
+}
+
 #[inline(always)]
 fn encode_loop<'a, 'b, T, F>(
     utf8_mult: usize,
     chain: &'a mut Vec<String>,
     buf: &'b [T],
-    get_char: F) -> Result<&'a str, u32>
+    get_char: F) -> Result<&'a str, (u32, usize)>
         where
             F: Fn(T) -> Option<char>,
             T: Copy + Into<u32>
 {
     let dest = get_dest(chain, utf8_mult * buf.len());
     let last = dest.len();
-    // for &b in buf.iter() {
-    //     // Checking for validity is not optional:
-    //     // >>> for n in range(2 ** 16):
-    //     // >>>     chr(n).encode('utf-8')
-    //     // UnicodeEncodeError: 'utf-8' codec can't encode character '\ud800'
-    //     //   in position 0: surrogates not allowed
-    //     match get_char(b) {
-    //         Some(c) => dest.push(c),
-    //         None => {
-    //             dest.truncate(last);
-    //             return Err(b.into());
-    //         }
-    //     }
-    // }
-    // Ok(&dest[last..])
-    unsafe {
-        let v = dest.as_mut_vec();
-        v.set_len(v.capacity());
-        let mut index = last;
-        
-        for &b in buf.iter() {
+
+    // Checking for validity is not optional:
+    // >>> for n in range(2 ** 16):
+    // >>>     chr(n).encode('utf-8')
+    // UnicodeEncodeError: 'utf-8' codec can't encode character '\ud800'
+    //   in position 0: surrogates not allowed
+    //
+    // `dest`'s length is left untouched (at `last`) for the whole loop: we
+    // only ever write into its spare capacity, and only advance the length
+    // once, past the bytes we actually initialized. On error, `dest`'s
+    // length never moved, so there is nothing to roll back.
+    let written = {
+        let v = unsafe { dest.as_mut_vec() };
+        let spare = v.spare_capacity_mut();
+        let mut index = 0usize;
+        let mut err = None;
+        for (unit_index, &b) in buf.iter().enumerate() {
             let c = match get_char(b) {
                 Some(c) => c,
                 None => {
-                    v.set_len(last);
-                    return Err(b.into())
+                    err = Some((b.into(), unit_index));
+                    break;
                 }
             };
-            let utf_c_len = c.len_utf8();
-            match utf_c_len {
-                1 => {
-                    v[index] = c as u8;
-                },
-                2 => {
-                    let mut codepoint_buf = [0; 4];
-                    let bytes = c
-                        .encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) =
-                        *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) =
-                        *bytes.get_unchecked(1);
-                },
-                3 => {
-                    let mut codepoint_buf = [0; 4];
-                    let bytes = c
-                        .encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) =
-                        *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) =
-                        *bytes.get_unchecked(1);
-                    *v.get_unchecked_mut(index + 2) =
-                        *bytes.get_unchecked(2);
-                },
-                4 => {
-                    let mut codepoint_buf = [0; 4];
-                    let bytes = c
-                        .encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) =
-                        *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) =
-                        *bytes.get_unchecked(1);
-                    *v.get_unchecked_mut(index + 2) =
-                        *bytes.get_unchecked(2);
-                    *v.get_unchecked_mut(index + 3) =
-                        *bytes.get_unchecked(3);
-                },
-                _ => unreachable!()
-            }
-            index += utf_c_len;
+            let c_len = c.len_utf8();
+            let slice = unsafe {
+                uninit_prefix_as_mut_slice(&mut spare[index..], c_len) };
+            c.encode_utf8(slice);
+            index += c_len;
+        }
+        match err {
+            Some(e) => Err(e),
+            None => Ok(index),
         }
-        v.set_len(index);
+    };
+    match written {
+        Ok(index) => {
+            unsafe { dest.as_mut_vec().set_len(last + index) };
+            Ok(&dest[last..])
+        }
+        Err(e) => Err(e),
     }
-    Ok(&dest[last..])
+}
+
+/// Returns the length of the leading run of `buf` containing only ASCII
+/// (< 0x80) bytes. Written as a tight, branch-predictable loop so the
+/// compiler can auto-vectorize it.
+#[inline(always)]
+fn ascii_prefix_len(buf: &[u8]) -> usize {
+    buf.iter().take_while(|&&byte| byte < 0x80).count()
 }
 
 /// Convert a Py_UCS1 string to UTF-8.
 /// Returns a `buf_out` borrowed ptr of `size_out` len.
 /// The buffer is borrowed from `b`.
+///
+/// UCS1 is the hot path for ordinary ASCII table/column names and symbol
+/// values, and any byte `< 0x80` is a verbatim one-byte copy. The leading
+/// ASCII run is therefore `memcpy`'d straight into the destination with no
+/// per-character branching, and only the Latin-1 tail (bytes in
+/// `0x80..=0xFF`, which expand to two UTF-8 bytes) goes through the general,
+/// per-character path. For a fully-ASCII string this skips all codepoint
+/// width bookkeeping.
 #[no_mangle]
 pub unsafe extern "C" fn qdb_ucs1_to_utf8(
         b: *mut qdb_pystr_buf,
@@ -201,13 +198,28 @@ pub unsafe extern "C" fn qdb_ucs1_to_utf8(
     let b = &mut *b;
     let i = from_raw_parts(input, count);
 
+    let ascii_len = ascii_prefix_len(i);
+    let rest = &i[ascii_len..];
+
     // len(chr(2 ** 8 - 1).encode('utf-8')) == 2
     let utf8_mult = 2;
-    let res = encode_loop(
-        utf8_mult,
-        &mut b.0,
-        i,
-        |c| Some(c as char)).unwrap();
+    let dest = get_dest(&mut b.0, utf8_mult * i.len());
+    let last = dest.len();
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        uninit_prefix_as_mut_slice(spare, ascii_len).copy_from_slice(&i[..ascii_len]);
+        let mut index = ascii_len;
+        for &byte in rest {
+            let c = byte as char;
+            let c_len = c.len_utf8();
+            let slice = uninit_prefix_as_mut_slice(&mut spare[index..], c_len);
+            c.encode_utf8(slice);
+            index += c_len;
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
     *size_out = res.len();
     *buf_out = res.as_ptr() as *const c_char;
 }
@@ -215,15 +227,17 @@ pub unsafe extern "C" fn qdb_ucs1_to_utf8(
 /// Convert a Py_UCS2 string to UTF-8.
 /// Returns a `buf_out` borrowed ptr of `size_out` len.
 /// The buffer is borrowed from `b`.
-/// In case of errors, returns `false` and bad_codepoint_out is set to the
-/// offending codepoint.
+/// In case of errors, returns `false`, `bad_codepoint_out` is set to the
+/// offending codepoint and `bad_codepoint_index_out` is set to its
+/// zero-based index within `input`.
 #[no_mangle]
 pub unsafe extern "C" fn qdb_ucs2_to_utf8(b: *mut qdb_pystr_buf,
         count: usize,
         input: *const u16,
         size_out: *mut usize,
         buf_out: *mut *const c_char,
-        bad_codepoint_out: *mut u32) -> bool {
+        bad_codepoint_out: *mut u32,
+        bad_codepoint_index_out: *mut usize) -> bool {
     let b = &mut *b;
     let i = from_raw_parts(input, count);
 
@@ -240,8 +254,9 @@ pub unsafe extern "C" fn qdb_ucs2_to_utf8(b: *mut qdb_pystr_buf,
             *buf_out = s.as_ptr() as *const c_char;
             true
         }
-        Err(bad) => {
+        Err((bad, index)) => {
             *bad_codepoint_out = bad;
+            *bad_codepoint_index_out = index;
             false
         }
     }
@@ -250,15 +265,17 @@ pub unsafe extern "C" fn qdb_ucs2_to_utf8(b: *mut qdb_pystr_buf,
 /// Convert a Py_UCS4 string to UTF-8.
 /// Returns a `buf_out` borrowed ptr of `size_out` len.
 /// The buffer is borrowed from `b`.
-/// In case of errors, returns `false` and bad_codepoint_out is set to the
-/// offending codepoint.
+/// In case of errors, returns `false`, `bad_codepoint_out` is set to the
+/// offending codepoint and `bad_codepoint_index_out` is set to its
+/// zero-based index within `input`.
 #[no_mangle]
 pub unsafe extern "C" fn qdb_ucs4_to_utf8(b: *mut qdb_pystr_buf,
         count: usize,
         input: *const u32,
         size_out: *mut usize,
         buf_out: *mut *const c_char,
-        bad_codepoint_out: *mut u32) -> bool {
+        bad_codepoint_out: *mut u32,
+        bad_codepoint_index_out: *mut usize) -> bool {
     let b = &mut *b;
     let i = from_raw_parts(input, count);
 
@@ -275,12 +292,502 @@ pub unsafe extern "C" fn qdb_ucs4_to_utf8(b: *mut qdb_pystr_buf,
             *buf_out = s.as_ptr() as *const c_char;
             true
         }
-        Err(bad) => {
+        Err((bad, index)) => {
             *bad_codepoint_out = bad;
+            *bad_codepoint_index_out = index;
             false
         }
     }
 }
 
+/// Selects how a lone (unpaired) surrogate code point is handled by the
+/// `qdb_ucsX_to_utf8_wtf8` family. A "lone" surrogate is a high surrogate
+/// (0xD800-0xDBFF) not immediately followed by a low surrogate
+/// (0xDC00-0xDFFF), or a low surrogate not preceded by a high one.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum qdb_lone_surrogate_policy {
+    /// Substitute the lone surrogate with the U+FFFD replacement character.
+    Replace = 0,
+    /// Preserve the lone surrogate as its raw 3-byte WTF-8 encoding.
+    Preserve = 1,
+}
+
+const REPLACEMENT_UTF8: [u8; 3] = [0xEF, 0xBF, 0xBD];
+
+/// Encode a lone surrogate `cp` (0xD800-0xDFFF) as the 3-byte sequence that
+/// `cp` would take as an ordinary UTF-8 codepoint. This is the WTF-8 encoding
+/// of an unpaired surrogate: https://simonsapin.github.io/wtf-8/
+fn encode_lone_surrogate(cp: u32) -> [u8; 3] {
+    [
+        0xE0 | ((cp >> 12) & 0x0F) as u8,
+        0x80 | ((cp >> 6) & 0x3F) as u8,
+        0x80 | (cp & 0x3F) as u8,
+    ]
+}
+
+/// Write `bytes` into `spare`, a `Vec<u8>`'s uninitialized spare-capacity
+/// slice, starting at `index`, returning the new index. Safety:
+/// `index + bytes.len()` must be within `spare`'s length.
+#[inline(always)]
+unsafe fn write_bytes_at(spare: &mut [MaybeUninit<u8>], index: usize, bytes: &[u8]) -> usize {
+    uninit_prefix_as_mut_slice(&mut spare[index..], bytes.len()).copy_from_slice(bytes);
+    index + bytes.len()
+}
+
+/// Combine a UTF-16 surrogate pair into the scalar value it encodes.
+fn combine_surrogates(hi: u16, lo: u16) -> u32 {
+    0x10000 + (((hi - 0xD800) as u32) << 10) + ((lo - 0xDC00) as u32)
+}
+
+#[inline(always)]
+fn is_high_surrogate(c: u16) -> bool {
+    (0xD800..=0xDBFF).contains(&c)
+}
+
+#[inline(always)]
+fn is_low_surrogate(c: u16) -> bool {
+    (0xDC00..=0xDFFF).contains(&c)
+}
+
+/// Convert a Py_UCS2 string to UTF-8, recombining surrogate pairs into
+/// their astral scalar value rather than rejecting them. A lone surrogate
+/// (no matching pair) is handled according to `policy`.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs2_to_utf8_wtf8(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u16,
+        policy: qdb_lone_surrogate_policy,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char) {
+    let b = &mut *b;
+    let buf = from_raw_parts(input, count);
+
+    // Worst case is 3 bytes/unit: either a lone surrogate (3 bytes for 1
+    // unit) or a combined pair (4 bytes for 2 units).
+    let utf8_mult = 3;
+    let dest = get_dest(&mut b.0, utf8_mult * buf.len());
+    let last = dest.len();
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let unit = buf[pos];
+            if is_high_surrogate(unit) {
+                if let Some(&next) = buf.get(pos + 1) {
+                    if is_low_surrogate(next) {
+                        let cp = combine_surrogates(unit, next);
+                        let c = char::from_u32(cp).unwrap();
+                        let mut codepoint_buf = [0u8; 4];
+                        let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                        index = write_bytes_at(spare, index, bytes);
+                        pos += 2;
+                        continue;
+                    }
+                }
+                index = match policy {
+                    qdb_lone_surrogate_policy::Replace =>
+                        write_bytes_at(spare, index, &REPLACEMENT_UTF8),
+                    qdb_lone_surrogate_policy::Preserve =>
+                        write_bytes_at(spare, index, &encode_lone_surrogate(unit as u32)),
+                };
+                pos += 1;
+            } else if is_low_surrogate(unit) {
+                index = match policy {
+                    qdb_lone_surrogate_policy::Replace =>
+                        write_bytes_at(spare, index, &REPLACEMENT_UTF8),
+                    qdb_lone_surrogate_policy::Preserve =>
+                        write_bytes_at(spare, index, &encode_lone_surrogate(unit as u32)),
+                };
+                pos += 1;
+            } else {
+                let c = char::from_u32(unit as u32).unwrap();
+                let mut codepoint_buf = [0u8; 4];
+                let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                index = write_bytes_at(spare, index, bytes);
+                pos += 1;
+            }
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+}
+
+/// Convert a Py_UCS4 string to UTF-8 in WTF-8 mode: an adjacent surrogate
+/// pair is combined into the astral scalar value it encodes, and a lone
+/// surrogate (0xD800-0xDFFF) is handled according to `policy` instead of aborting the
+/// whole conversion. Any other out-of-range value (> 0x10FFFF) still fails
+/// the conversion and leaves the buffer untouched, exactly as
+/// `qdb_ucs4_to_utf8` does.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs4_to_utf8_wtf8(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u32,
+        policy: qdb_lone_surrogate_policy,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char,
+        bad_codepoint_out: *mut u32) -> bool {
+    let b = &mut *b;
+    let buf = from_raw_parts(input, count);
+
+    let utf8_mult = 4;
+    let dest = get_dest(&mut b.0, utf8_mult * buf.len());
+    let last = dest.len();
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let cp = buf[pos];
+            if (0xD800..=0xDBFF).contains(&cp) {
+                if let Some(&next) = buf.get(pos + 1) {
+                    if (0xDC00..=0xDFFF).contains(&next) {
+                        let c = char::from_u32(combine_surrogates(cp as u16, next as u16)).unwrap();
+                        let mut codepoint_buf = [0u8; 4];
+                        let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                        index = write_bytes_at(spare, index, bytes);
+                        pos += 2;
+                        continue;
+                    }
+                }
+            }
+            pos += 1;
+            if (0xD800..=0xDFFF).contains(&cp) {
+                index = match policy {
+                    qdb_lone_surrogate_policy::Replace =>
+                        write_bytes_at(spare, index, &REPLACEMENT_UTF8),
+                    qdb_lone_surrogate_policy::Preserve =>
+                        write_bytes_at(spare, index, &encode_lone_surrogate(cp)),
+                };
+                continue;
+            }
+            let c = match char::from_u32(cp) {
+                Some(c) => c,
+                None => {
+                    *bad_codepoint_out = cp;
+                    return false;
+                }
+            };
+            let mut codepoint_buf = [0u8; 4];
+            let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+            index = write_bytes_at(spare, index, bytes);
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    true
+}
+
+/// Encode every element of `buf` to UTF-8, substituting the U+FFFD
+/// replacement character for any code point rejected by `get_char` instead
+/// of failing. Returns the encoded string together with the number of
+/// substitutions made.
+#[inline(always)]
+fn encode_loop_lossy<'a, T, F>(
+    utf8_mult: usize,
+    chain: &'a mut Vec<String>,
+    buf: &[T],
+    get_char: F) -> (&'a str, usize)
+        where
+            F: Fn(T) -> Option<char>,
+            T: Copy
+{
+    let dest = get_dest(chain, utf8_mult * buf.len());
+    let last = dest.len();
+    let mut substitutions = 0usize;
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        for &b in buf.iter() {
+            let c = get_char(b);
+            index = match c {
+                Some(c) => {
+                    let mut codepoint_buf = [0u8; 4];
+                    let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                    write_bytes_at(spare, index, bytes)
+                }
+                None => {
+                    substitutions += 1;
+                    write_bytes_at(spare, index, &REPLACEMENT_UTF8)
+                }
+            };
+        }
+        v.set_len(last + index);
+    }
+    (&dest[last..], substitutions)
+}
+
+/// Convert a Py_UCS1 string to UTF-8. Never fails: UCS1 code points always
+/// map onto a valid Unicode scalar value. `substitutions_out` is always set
+/// to 0 and exists only for symmetry with `qdb_ucs2_to_utf8_lossy` and
+/// `qdb_ucs4_to_utf8_lossy`.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs1_to_utf8_lossy(
+        b: *mut qdb_pystr_buf,
+        count: usize, input: *const u8,
+        size_out: *mut usize, buf_out: *mut *const c_char,
+        substitutions_out: *mut usize) {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    let utf8_mult = 2;
+    let (res, substitutions) = encode_loop_lossy(
+        utf8_mult,
+        &mut b.0,
+        i,
+        |c| Some(c as char));
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
+/// Convert a Py_UCS2 string to UTF-8, never failing: a high surrogate
+/// (0xD800-0xDBFF) immediately followed by a low surrogate (0xDC00-0xDFFF)
+/// is recombined into the astral scalar value it encodes (modeled on
+/// `char::decode_utf16`), and any other lone surrogate is substituted with
+/// the U+FFFD replacement character instead of aborting the conversion.
+/// `substitutions_out` is set to the number of replacements made.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs2_to_utf8_lossy(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u16,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char,
+        substitutions_out: *mut usize) {
+    let b = &mut *b;
+    let buf = from_raw_parts(input, count);
+
+    // UCS2 worst case stays 3 bytes/unit: a recombined surrogate pair costs
+    // 4 bytes for 2 units, i.e. 2 bytes/unit, which is cheaper than a lone
+    // surrogate's 3-byte replacement.
+    let utf8_mult = 3;
+    let dest = get_dest(&mut b.0, utf8_mult * buf.len());
+    let last = dest.len();
+    let mut substitutions = 0usize;
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let unit = buf[pos];
+            if is_high_surrogate(unit) {
+                if let Some(&next) = buf.get(pos + 1) {
+                    if is_low_surrogate(next) {
+                        let cp = combine_surrogates(unit, next);
+                        let c = char::from_u32(cp).unwrap();
+                        let mut codepoint_buf = [0u8; 4];
+                        let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                        index = write_bytes_at(spare, index, bytes);
+                        pos += 2;
+                        continue;
+                    }
+                }
+                substitutions += 1;
+                index = write_bytes_at(spare, index, &REPLACEMENT_UTF8);
+                pos += 1;
+            } else if is_low_surrogate(unit) {
+                substitutions += 1;
+                index = write_bytes_at(spare, index, &REPLACEMENT_UTF8);
+                pos += 1;
+            } else {
+                let c = char::from_u32(unit as u32).unwrap();
+                let mut codepoint_buf = [0u8; 4];
+                let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                index = write_bytes_at(spare, index, bytes);
+                pos += 1;
+            }
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
+/// Convert a Py_UCS4 string to UTF-8, substituting U+FFFD for any code
+/// point that cannot be encoded (e.g. a surrogate or a value >= 0x110000)
+/// instead of failing. `substitutions_out` is set to the number of
+/// replacements made.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs4_to_utf8_lossy(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u32,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char,
+        substitutions_out: *mut usize) {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    // Max 4 bytes allowed by RFC: https://www.rfc-editor.org/rfc/rfc3629#page-4
+    let utf8_mult = 4;
+    let (res, substitutions) = encode_loop_lossy(
+        utf8_mult,
+        &mut b.0,
+        i,
+        |c| char::from_u32(c));
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
+/// Convert a genuine UTF-16 code unit slice to UTF-8, combining surrogate
+/// pairs into the single scalar value they encode, unlike `qdb_ucs2_to_utf8`
+/// which treats every `u16` as an independent (and never-paired) UCS-2 code
+/// point. In case of an unpaired high surrogate (at the end of `input`, or
+/// not followed by a low surrogate) or a stray low surrogate, returns
+/// `false`, `bad_codepoint_out` is set to the offending code unit and
+/// `bad_codepoint_index_out` to its zero-based index within `input`, and the
+/// buffer is left untouched exactly as `qdb_ucs2_to_utf8` does.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_utf16_to_utf8(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u16,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char,
+        bad_codepoint_out: *mut u32,
+        bad_codepoint_index_out: *mut usize) -> bool {
+    let b = &mut *b;
+    let buf = from_raw_parts(input, count);
+
+    // Worst case 3 bytes/unit: any non-surrogate unit from 0x800 up encodes
+    // to 3 bytes, while a surrogate pair takes 4 bytes for 2 units.
+    let utf8_mult = 3;
+    let dest = get_dest(&mut b.0, utf8_mult * buf.len());
+    let last = dest.len();
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let unit = buf[pos];
+            if is_high_surrogate(unit) {
+                let Some(&next) = buf.get(pos + 1) else {
+                    *bad_codepoint_out = unit as u32;
+                    *bad_codepoint_index_out = pos;
+                    return false;
+                };
+                if !is_low_surrogate(next) {
+                    *bad_codepoint_out = unit as u32;
+                    *bad_codepoint_index_out = pos;
+                    return false;
+                }
+                let cp = combine_surrogates(unit, next);
+                let c = char::from_u32(cp).unwrap();
+                let mut codepoint_buf = [0u8; 4];
+                let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                index = write_bytes_at(spare, index, bytes);
+                pos += 2;
+            } else if is_low_surrogate(unit) {
+                *bad_codepoint_out = unit as u32;
+                *bad_codepoint_index_out = pos;
+                return false;
+            } else {
+                let c = char::from_u32(unit as u32).unwrap();
+                let mut codepoint_buf = [0u8; 4];
+                let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                index = write_bytes_at(spare, index, bytes);
+                pos += 1;
+            }
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    true
+}
+
+/// Lossy variant of `qdb_utf16_to_utf8`: an unpaired high or stray low
+/// surrogate is substituted with U+FFFD instead of failing the whole
+/// conversion. `substitutions_out` is set to the number of replacements
+/// made.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_utf16_to_utf8_lossy(
+        b: *mut qdb_pystr_buf,
+        count: usize,
+        input: *const u16,
+        size_out: *mut usize,
+        buf_out: *mut *const c_char,
+        substitutions_out: *mut usize) {
+    let b = &mut *b;
+    let buf = from_raw_parts(input, count);
+
+    let utf8_mult = 3;
+    let dest = get_dest(&mut b.0, utf8_mult * buf.len());
+    let last = dest.len();
+    let mut substitutions = 0usize;
+    unsafe {
+        let v = dest.as_mut_vec();
+        let spare = v.spare_capacity_mut();
+        let mut index = 0;
+        let mut pos = 0;
+        while pos < buf.len() {
+            let unit = buf[pos];
+            if is_high_surrogate(unit) {
+                if let Some(&next) = buf.get(pos + 1) {
+                    if is_low_surrogate(next) {
+                        let cp = combine_surrogates(unit, next);
+                        let c = char::from_u32(cp).unwrap();
+                        let mut codepoint_buf = [0u8; 4];
+                        let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                        index = write_bytes_at(spare, index, bytes);
+                        pos += 2;
+                        continue;
+                    }
+                }
+                substitutions += 1;
+                index = write_bytes_at(spare, index, &REPLACEMENT_UTF8);
+                pos += 1;
+            } else if is_low_surrogate(unit) {
+                substitutions += 1;
+                index = write_bytes_at(spare, index, &REPLACEMENT_UTF8);
+                pos += 1;
+            } else {
+                let c = char::from_u32(unit as u32).unwrap();
+                let mut codepoint_buf = [0u8; 4];
+                let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+                index = write_bytes_at(spare, index, bytes);
+                pos += 1;
+            }
+        }
+        v.set_len(last + index);
+    }
+    let res = &dest[last..];
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
 #[cfg(test)]
 mod tests;
@@ -47,10 +47,11 @@ impl Buf {
         std::str::from_utf8(slice).unwrap()
     }
 
-    fn ucs2_to_utf8(&mut self, input: &[u16]) -> Result<&'static str, u32> {
+    fn ucs2_to_utf8(&mut self, input: &[u16]) -> Result<&'static str, (u32, usize)> {
         let mut size_out = 0;
         let mut buf_out = std::ptr::null();
         let mut bad_codepoint = 0u32;
+        let mut bad_codepoint_index = 0usize;
         let ok = unsafe {
                 qdb_ucs2_to_utf8(
                     self.buf,
@@ -58,7 +59,8 @@ impl Buf {
                     input.as_ptr(),
                     &mut size_out,
                     &mut buf_out,
-                    &mut bad_codepoint)
+                    &mut bad_codepoint,
+                    &mut bad_codepoint_index)
             };
         if ok {
             let slice = unsafe {
@@ -66,14 +68,15 @@ impl Buf {
             let msg = std::str::from_utf8(slice).unwrap();
             Ok(msg)
         } else {
-            Err(bad_codepoint)
+            Err((bad_codepoint, bad_codepoint_index))
         }
     }
 
-    fn ucs4_to_utf8(&mut self, input: &[u32]) -> Result<&'static str, u32> {
+    fn ucs4_to_utf8(&mut self, input: &[u32]) -> Result<&'static str, (u32, usize)> {
         let mut size_out = 0;
         let mut buf_out = std::ptr::null();
         let mut bad_codepoint = 0u32;
+        let mut bad_codepoint_index = 0usize;
         let ok = unsafe {
                 qdb_ucs4_to_utf8(
                     self.buf,
@@ -81,17 +84,146 @@ impl Buf {
                     input.as_ptr(),
                     &mut size_out,
                     &mut buf_out,
-                    &mut bad_codepoint)
+                    &mut bad_codepoint,
+                    &mut bad_codepoint_index)
             };
         if ok {
             let slice = unsafe {
                 from_raw_parts(buf_out as *const u8, size_out) };
             let msg = std::str::from_utf8(slice).unwrap();
             Ok(msg)
+        } else {
+            Err((bad_codepoint, bad_codepoint_index))
+        }
+    }
+
+    // In `Preserve` mode these may contain the raw 3-byte encoding of a
+    // lone surrogate, which is not valid UTF-8, so they return bytes
+    // rather than `&str`.
+
+    fn ucs2_to_utf8_wtf8(
+            &mut self,
+            input: &[u16],
+            policy: qdb_lone_surrogate_policy) -> &'static [u8] {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        unsafe {
+            qdb_ucs2_to_utf8_wtf8(
+                self.buf,
+                input.len(),
+                input.as_ptr(),
+                policy,
+                &mut size_out,
+                &mut buf_out);
+        }
+        unsafe {
+            from_raw_parts(buf_out as *const u8, size_out) }
+    }
+
+    fn ucs4_to_utf8_wtf8(
+            &mut self,
+            input: &[u32],
+            policy: qdb_lone_surrogate_policy) -> Result<&'static [u8], u32> {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        let mut bad_codepoint = 0u32;
+        let ok = unsafe {
+                qdb_ucs4_to_utf8_wtf8(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    policy,
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut bad_codepoint)
+            };
+        if ok {
+            let slice = unsafe {
+                from_raw_parts(buf_out as *const u8, size_out) };
+            Ok(slice)
         } else {
             Err(bad_codepoint)
         }
     }
+
+    fn ucs2_to_utf8_lossy(&mut self, input: &[u16]) -> (&'static str, usize) {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        let mut substitutions = 0usize;
+        unsafe {
+            qdb_ucs2_to_utf8_lossy(
+                self.buf,
+                input.len(),
+                input.as_ptr(),
+                &mut size_out,
+                &mut buf_out,
+                &mut substitutions);
+        }
+        let slice = unsafe {
+            from_raw_parts(buf_out as *const u8, size_out) };
+        (std::str::from_utf8(slice).unwrap(), substitutions)
+    }
+
+    fn ucs4_to_utf8_lossy(&mut self, input: &[u32]) -> (&'static str, usize) {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        let mut substitutions = 0usize;
+        unsafe {
+            qdb_ucs4_to_utf8_lossy(
+                self.buf,
+                input.len(),
+                input.as_ptr(),
+                &mut size_out,
+                &mut buf_out,
+                &mut substitutions);
+        }
+        let slice = unsafe {
+            from_raw_parts(buf_out as *const u8, size_out) };
+        (std::str::from_utf8(slice).unwrap(), substitutions)
+    }
+
+    fn utf16_to_utf8(&mut self, input: &[u16]) -> Result<&'static str, (u32, usize)> {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        let mut bad_codepoint = 0u32;
+        let mut bad_codepoint_index = 0usize;
+        let ok = unsafe {
+                qdb_utf16_to_utf8(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut bad_codepoint,
+                    &mut bad_codepoint_index)
+            };
+        if ok {
+            let slice = unsafe {
+                from_raw_parts(buf_out as *const u8, size_out) };
+            let msg = std::str::from_utf8(slice).unwrap();
+            Ok(msg)
+        } else {
+            Err((bad_codepoint, bad_codepoint_index))
+        }
+    }
+
+    fn utf16_to_utf8_lossy(&mut self, input: &[u16]) -> (&'static str, usize) {
+        let mut size_out = 0;
+        let mut buf_out = std::ptr::null();
+        let mut substitutions = 0usize;
+        unsafe {
+            qdb_utf16_to_utf8_lossy(
+                self.buf,
+                input.len(),
+                input.as_ptr(),
+                &mut size_out,
+                &mut buf_out,
+                &mut substitutions);
+        }
+        let slice = unsafe {
+            from_raw_parts(buf_out as *const u8, size_out) };
+        (std::str::from_utf8(slice).unwrap(), substitutions)
+    }
 }
 
 impl Drop for Buf {
@@ -139,6 +271,19 @@ fn test_ucs1() {
         chain: 1, string: s2.len() + s3.len() });
 }
 
+#[test]
+fn test_ucs1_ascii_fast_path_boundary() {
+    let mut b = Buf::new();
+
+    // Non-ASCII right at the start: the fast path's leading run is empty.
+    let s1 = b.ucs1_to_utf8(b"\xb5z");
+    assert_eq!(s1, "µz");
+
+    // All-ASCII: the fast path consumes the whole input, no per-char tail.
+    let s2 = b.ucs1_to_utf8(b"hello world");
+    assert_eq!(s2, "hello world");
+}
+
 #[test]
 fn test_resize_and_truncate() {
     let mut b = Buf::new();
@@ -220,7 +365,7 @@ fn test_ucs2() {
     let before_pos = b.tell();
     let s5 = b.ucs2_to_utf8(&[0x061, 0xd800]);
     assert!(s5.is_err());
-    assert_eq!(s5.unwrap_err(), 0xd800 as u32);
+    assert_eq!(s5.unwrap_err(), (0xd800 as u32, 1));
 
     // Even though 0x061 (ASCII char 'a') was valid and successfully encoded,
     // we also want to be sure that the buffer was not modified and appended to.
@@ -290,7 +435,7 @@ fn test_ucs4() {
     let before_pos = b.tell();
     let s6 = b.ucs4_to_utf8(&[0x061, 0xd800]);
     assert!(s6.is_err());
-    assert_eq!(s6.unwrap_err(), 0xd800 as u32);
+    assert_eq!(s6.unwrap_err(), (0xd800 as u32, 1));
 
     // Even though 0x061 (ASCII char 'a') was valid and successfully encoded,
     // we also want to be sure that the buffer was not modified and appended to.
@@ -300,7 +445,7 @@ fn test_ucs4() {
     let before_pos = b.tell();
     let s7 = b.ucs4_to_utf8(&[0x061, 0x110000]);
     assert!(s7.is_err());
-    assert_eq!(s7.unwrap_err(), 0x110000);
+    assert_eq!(s7.unwrap_err(), (0x110000, 1));
 
     // Even though 0x061 (ASCII char 'a') was valid and successfully encoded,
     // we also want to be sure that the buffer was not modified and appended to.
@@ -312,4 +457,181 @@ fn test_ucs4() {
     assert_eq!(b.tell(), qdb_pystr_pos {
         chain: 1,
         string: [s1, s2, s3, s4, s5, s8].iter().map(|s| s.len()).sum() });
+}
+
+#[test]
+fn test_encode_loop_covers_all_utf8_widths_and_rolls_back_on_error() {
+    let mut b = Buf::new();
+
+    // One code point of each UTF-8 width: 'a' (1), 'µ' (2), '嚜' (3), '💩' (4).
+    let s = b.ucs4_to_utf8(&[0x61, 0xb5, 0x569c, 0x1f4a9]).unwrap();
+    assert_eq!(s, "aµ嚜💩");
+    assert_eq!(s.len(), 1 + 2 + 3 + 4);
+
+    // A rejected code point must leave the buffer exactly as it was: no
+    // partially-written bytes from earlier in the same call are exposed.
+    let before_pos = b.tell();
+    let err = b.ucs4_to_utf8(&[0x61, 0xb5, 0xd800]);
+    assert!(err.is_err());
+    assert_eq!(err.unwrap_err(), (0xd800, 2));
+    assert_eq!(b.tell(), before_pos);
+}
+
+#[test]
+fn test_ucs2_to_utf8_lossy_substitutes_replacement_character() {
+    let mut b = Buf::new();
+    let (s, substitutions) = b.ucs2_to_utf8_lossy(&[0x61, 0xd800, 0x62, 0xdfff]);
+    assert_eq!(s, "a\u{fffd}b\u{fffd}");
+    assert_eq!(substitutions, 2);
+}
+
+#[test]
+fn test_ucs2_to_utf8_lossy_combines_surrogate_pairs() {
+    let mut b = Buf::new();
+    // "a💩b", where 💩 is encoded as the surrogate pair 0xd83d 0xdca9, mixed
+    // with a lone high surrogate that has no pair to combine with.
+    let (s, substitutions) = b.ucs2_to_utf8_lossy(&[0x61, 0xd83d, 0xdca9, 0x62, 0xd800]);
+    assert_eq!(s, "a💩b\u{fffd}");
+    assert_eq!(substitutions, 1);
+}
+
+#[test]
+fn test_ucs4_to_utf8_lossy_substitutes_replacement_character() {
+    let mut b = Buf::new();
+    let (s, substitutions) = b.ucs4_to_utf8_lossy(&[0x61, 0x110000, 0x62]);
+    assert_eq!(s, "a\u{fffd}b");
+    assert_eq!(substitutions, 1);
+
+    // No bad code points: zero substitutions, same buffer/position semantics
+    // as the strict path.
+    let before_pos = b.tell();
+    let (s2, substitutions2) = b.ucs4_to_utf8_lossy(&[0x63, 0x64]);
+    assert_eq!(s2, "cd");
+    assert_eq!(substitutions2, 0);
+    assert_eq!(b.tell(), qdb_pystr_pos {
+        chain: before_pos.chain, string: before_pos.string + 2 });
+}
+
+#[test]
+fn test_utf16_to_utf8_combines_surrogate_pairs() {
+    let mut b = Buf::new();
+    // "a💩b", where 💩 is encoded as the surrogate pair 0xd83d 0xdca9.
+    let s = b.utf16_to_utf8(&[0x61, 0xd83d, 0xdca9, 0x62]).unwrap();
+    assert_eq!(s, "a💩b");
+}
+
+#[test]
+fn test_utf16_to_utf8_rejects_unpaired_high_surrogate_at_end() {
+    let mut b = Buf::new();
+    // Prime the chain with a successful call first: a fresh, empty chain
+    // always gains its first (initially empty) entry on the very next
+    // call, pass or fail, so `before_pos` needs to be taken once the chain
+    // is already allocated for the "nothing appended on failure" check
+    // below to be meaningful.
+    b.utf16_to_utf8(&[0x61]).unwrap();
+    let before_pos = b.tell();
+    let err = b.utf16_to_utf8(&[0x61, 0xd800]).unwrap_err();
+    assert_eq!(err, (0xd800, 1));
+    assert_eq!(b.tell(), before_pos);
+}
+
+#[test]
+fn test_utf16_to_utf8_rejects_high_surrogate_not_followed_by_low() {
+    let mut b = Buf::new();
+    b.utf16_to_utf8(&[0x61]).unwrap();
+    let before_pos = b.tell();
+    let err = b.utf16_to_utf8(&[0x61, 0xd800, 0x62]).unwrap_err();
+    assert_eq!(err, (0xd800, 1));
+    assert_eq!(b.tell(), before_pos);
+}
+
+#[test]
+fn test_utf16_to_utf8_rejects_stray_low_surrogate() {
+    let mut b = Buf::new();
+    b.utf16_to_utf8(&[0x61]).unwrap();
+    let before_pos = b.tell();
+    let err = b.utf16_to_utf8(&[0x61, 0xdc00, 0x62]).unwrap_err();
+    assert_eq!(err, (0xdc00, 1));
+    assert_eq!(b.tell(), before_pos);
+}
+
+#[test]
+fn test_utf16_to_utf8_lossy_substitutes_replacement_character() {
+    let mut b = Buf::new();
+    let (s, substitutions) = b.utf16_to_utf8_lossy(&[0x61, 0xd800, 0x62, 0xdc00]);
+    assert_eq!(s, "a\u{fffd}b\u{fffd}");
+    assert_eq!(substitutions, 2);
+
+    let (s2, substitutions2) = b.utf16_to_utf8_lossy(&[0x61, 0xd83d, 0xdca9]);
+    assert_eq!(s2, "a💩");
+    assert_eq!(substitutions2, 0);
+}
+
+#[test]
+fn test_bad_codepoint_index_points_at_offending_unit() {
+    let mut b = Buf::new();
+
+    let err = b.ucs2_to_utf8(&[0x61, 0x62, 0x63, 0xd800, 0x64]).unwrap_err();
+    assert_eq!(err, (0xd800, 3));
+
+    let err = b.ucs4_to_utf8(&[0x61, 0x62, 0x110000, 0x63]).unwrap_err();
+    assert_eq!(err, (0x110000, 2));
+}
+
+#[test]
+fn test_ucs2_wtf8_combines_surrogate_pairs() {
+    let mut b = Buf::new();
+
+    // 0x1f4a9 == "💩", encoded as the surrogate pair 0xd83d 0xdca9.
+    let s1 = b.ucs2_to_utf8_wtf8(
+        &[0x61, 0xd83d, 0xdca9, 0x62],
+        qdb_lone_surrogate_policy::Replace);
+    assert_eq!(s1, "a💩b".as_bytes());
+}
+
+#[test]
+fn test_ucs2_wtf8_lone_surrogate_replace() {
+    let mut b = Buf::new();
+    let s1 = b.ucs2_to_utf8_wtf8(
+        &[0x61, 0xd800, 0x62],
+        qdb_lone_surrogate_policy::Replace);
+    assert_eq!(s1, "a\u{fffd}b".as_bytes());
+}
+
+#[test]
+fn test_ucs2_wtf8_lone_surrogate_preserve() {
+    let mut b = Buf::new();
+    let s1 = b.ucs2_to_utf8_wtf8(
+        &[0x61, 0xd800, 0x62],
+        qdb_lone_surrogate_policy::Preserve);
+    assert_eq!(s1, &[b'a', 0xED, 0xA0, 0x80, b'b']);
+}
+
+#[test]
+fn test_ucs2_wtf8_high_surrogate_at_end() {
+    let mut b = Buf::new();
+    let s1 = b.ucs2_to_utf8_wtf8(
+        &[0x61, 0xd800],
+        qdb_lone_surrogate_policy::Replace);
+    assert_eq!(s1, "a\u{fffd}".as_bytes());
+}
+
+#[test]
+fn test_ucs4_wtf8_lone_surrogate_and_rollback() {
+    let mut b = Buf::new();
+
+    let s1 = b.ucs4_to_utf8_wtf8(
+        &[0x61, 0xd800, 0x62],
+        qdb_lone_surrogate_policy::Preserve).unwrap();
+    assert_eq!(s1, &[b'a', 0xED, 0xA0, 0x80, b'b']);
+
+    // Anything beyond the valid codepoint range still fails, even in WTF-8
+    // mode, and leaves the buffer untouched.
+    let before_pos = b.tell();
+    let s2 = b.ucs4_to_utf8_wtf8(
+        &[0x61, 0x110000],
+        qdb_lone_surrogate_policy::Preserve);
+    assert!(s2.is_err());
+    assert_eq!(s2.unwrap_err(), 0x110000);
+    assert_eq!(b.tell(), before_pos);
 }
\ No newline at end of file
@@ -16,25 +16,86 @@ pub unsafe extern "C" fn questdb_pystr_converter_free(c: *mut converter) {
     }
 }
 
-fn encode_ucs1(dest: &mut String, buf: &[u8]) -> bool {
-    write!(dest, "'nyi").unwrap();
-    false
+/// Which ILP grammar production a converted string is destined for, since
+/// each has its own escaping rules.
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum qdb_pystr_target {
+    /// A table or column name: `,`, ` `, `=` and `\` are backslash-escaped.
+    Name = 0,
+    /// A symbol (tag) value: same escaping rules as `Name`.
+    Symbol = 1,
+    /// A double-quoted string field value: `"` and `\` are backslash-escaped.
+    StrValue = 2,
+}
+
+/// Write `c`, escaped for `target`, to `dest`.
+fn write_escaped(dest: &mut String, c: char, target: qdb_pystr_target) {
+    match target {
+        qdb_pystr_target::Name | qdb_pystr_target::Symbol => match c {
+            ',' | ' ' | '=' | '\\' => {
+                dest.push('\\');
+                dest.push(c);
+            }
+            '\n' => dest.push_str("\\n"),
+            '\r' => dest.push_str("\\r"),
+            _ => dest.push(c),
+        },
+        qdb_pystr_target::StrValue => match c {
+            '"' | '\\' => {
+                dest.push('\\');
+                dest.push(c);
+            }
+            '\n' => dest.push_str("\\n"),
+            '\r' => dest.push_str("\\r"),
+            _ => dest.push(c),
+        },
+    }
 }
 
-fn encode_ucs2(dest: &mut String, buf: &[u16]) -> bool {
-    write!(dest, "'nyi").unwrap();
-    false
+fn encode_ucs1(dest: &mut String, buf: &[u8], target: qdb_pystr_target) -> bool {
+    for &b in buf {
+        write_escaped(dest, b as char, target);
+    }
+    true
 }
 
-fn encode_ucs4(dest: &mut String, buf: &[u32]) -> bool {
-    write!(dest, "'nyi").unwrap();
-    false
+fn encode_ucs2(dest: &mut String, buf: &[u16], target: qdb_pystr_target) -> bool {
+    for &unit in buf {
+        match char::from_u32(unit as u32) {
+            Some(c) => write_escaped(dest, c, target),
+            None => {
+                dest.clear();
+                write!(dest, "invalid codepoint: surrogate U+{:04X}", unit).unwrap();
+                return false;
+            }
+        }
+    }
+    true
 }
 
-/// Converts a Python string to a UTF8 buffer.
+fn encode_ucs4(dest: &mut String, buf: &[u32], target: qdb_pystr_target) -> bool {
+    for &cp in buf {
+        match char::from_u32(cp) {
+            Some(c) => write_escaped(dest, c, target),
+            None => {
+                dest.clear();
+                write!(dest, "invalid codepoint: U+{:X}", cp).unwrap();
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Converts a Python string to a UTF8 buffer, escaping it for ILP in the
+/// same pass as it is transcoded.
 /// * Width is 1 for UCS1, 2 for UCS2, 4 for UCS4.
 /// * Count is the number of code points.
 /// * Input is the pointer to the UCS{1,2,4} data.
+/// * Target selects the escaping rules to apply (name, symbol, or a
+///   double-quoted string value).
 /// * size_out is the resulting size in bytes of the UTF8 string.
 /// * buf_out is set to point to the UTF8 string.
 /// Returns true for success of false for failure.
@@ -42,20 +103,23 @@ fn encode_ucs4(dest: &mut String, buf: &[u32]) -> bool {
 #[no_mangle]
 pub unsafe extern "C" fn questdb_pystr_to_convert(
         c: *mut converter,
-        width: u8, count: usize, input: *const c_void,
+        width: u8, count: usize, input: *const c_void, target: qdb_pystr_target,
         size_out: *mut usize, buf_out: *mut *const c_char) -> bool {
     let sbuf: &mut String = &mut (*c).0;
     sbuf.clear();
     let ok = match width {
         1 => encode_ucs1(
             sbuf,
-            std::slice::from_raw_parts(input as *const u8, count)),
+            std::slice::from_raw_parts(input as *const u8, count),
+            target),
         2 => encode_ucs2(
             sbuf,
-            std::slice::from_raw_parts(input as *const u16, count)),
+            std::slice::from_raw_parts(input as *const u16, count),
+            target),
         4 => encode_ucs4(
             sbuf,
-            std::slice::from_raw_parts(input as *const u32, count)),
+            std::slice::from_raw_parts(input as *const u32, count),
+            target),
         _ => {
             write!(sbuf, "Unsupported width: {}", width).unwrap();
             false
@@ -70,9 +134,112 @@ pub unsafe extern "C" fn questdb_pystr_to_convert(
 mod tests {
     use super::*;
 
+    struct Converter {
+        c: *mut converter,
+    }
+
+    impl Converter {
+        fn new() -> Self {
+            Self {
+                c: unsafe { questdb_pystr_converter_new() },
+            }
+        }
+
+        fn convert(&mut self, width: u8, count: usize, input: *const c_void, target: qdb_pystr_target) -> Result<&str, &str> {
+            let mut size_out = 0usize;
+            let mut buf_out = std::ptr::null();
+            let ok = unsafe {
+                questdb_pystr_to_convert(self.c, width, count, input, target, &mut size_out, &mut buf_out)
+            };
+            let slice = unsafe { std::slice::from_raw_parts(buf_out as *const u8, size_out) };
+            let s = std::str::from_utf8(slice).unwrap();
+            if ok { Ok(s) } else { Err(s) }
+        }
+
+        fn convert_ucs1(&mut self, input: &[u8], target: qdb_pystr_target) -> Result<&str, &str> {
+            self.convert(1, input.len(), input.as_ptr() as *const c_void, target)
+        }
+
+        fn convert_ucs2(&mut self, input: &[u16], target: qdb_pystr_target) -> Result<&str, &str> {
+            self.convert(2, input.len(), input.as_ptr() as *const c_void, target)
+        }
+
+        fn convert_ucs4(&mut self, input: &[u32], target: qdb_pystr_target) -> Result<&str, &str> {
+            self.convert(4, input.len(), input.as_ptr() as *const c_void, target)
+        }
+    }
+
+    impl Drop for Converter {
+        fn drop(&mut self) {
+            unsafe { questdb_pystr_converter_free(self.c) }
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_ucs1_ucs2_ucs4() {
+        let mut c = Converter::new();
+        assert_eq!(c.convert_ucs1(b"hello", qdb_pystr_target::StrValue).unwrap(), "hello");
+        assert_eq!(c.convert_ucs2(&[0x61, 0x569c], qdb_pystr_target::StrValue).unwrap(), "a\u{569c}");
+        assert_eq!(c.convert_ucs4(&[0x61, 0x1f4a9], qdb_pystr_target::StrValue).unwrap(), "a\u{1f4a9}");
+    }
+
+    #[test]
+    fn test_escapes_name_and_symbol_specials() {
+        let mut c = Converter::new();
+        let input = "a,b c=d\\e".as_bytes();
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::Name).unwrap(),
+            "a\\,b\\ c\\=d\\\\e"
+        );
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::Symbol).unwrap(),
+            "a\\,b\\ c\\=d\\\\e"
+        );
+    }
+
+    #[test]
+    fn test_escapes_str_value_specials() {
+        let mut c = Converter::new();
+        let input = "a\"b\\c".as_bytes();
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::StrValue).unwrap(),
+            "a\\\"b\\\\c"
+        );
+
+        // Commas, spaces and `=` are left alone in a quoted string value.
+        let input = "a,b c=d".as_bytes();
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::StrValue).unwrap(),
+            "a,b c=d"
+        );
+    }
+
+    #[test]
+    fn test_escapes_newline_and_carriage_return() {
+        let mut c = Converter::new();
+        let input = "a\nb\rc".as_bytes();
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::Name).unwrap(),
+            "a\\nb\\rc"
+        );
+        assert_eq!(
+            c.convert_ucs1(input, qdb_pystr_target::StrValue).unwrap(),
+            "a\\nb\\rc"
+        );
+    }
+
+    #[test]
+    fn test_rejects_surrogate_codepoint() {
+        let mut c = Converter::new();
+        let err = c.convert_ucs2(&[0x61, 0xd800], qdb_pystr_target::StrValue).unwrap_err();
+        assert_eq!(err, "invalid codepoint: surrogate U+D800");
+    }
+
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_unsupported_width() {
+        let mut c = Converter::new();
+        let input = [0u8];
+        let err = c.convert(3, 1, input.as_ptr() as *const c_void, qdb_pystr_target::StrValue).unwrap_err();
+        assert_eq!(err, "Unsupported width: 3");
     }
 }
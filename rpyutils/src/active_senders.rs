@@ -23,9 +23,17 @@
  ******************************************************************************/
 
 use std::{
-    collections::VecDeque, ffi::c_int, fmt::Debug, ops::Sub, sync::{LazyLock, Mutex}, time::{Duration, Instant}
+    collections::VecDeque,
+    ffi::{c_int, c_void},
+    fmt::Debug,
+    ops::Sub,
+    sync::{atomic::AtomicU32, atomic::Ordering, LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
+use crossbeam_channel::{Receiver, Sender};
+
 type Slot = u32;
 
 struct Slots {
@@ -97,9 +105,37 @@ impl InstantLike for Instant {
     }
 }
 
-struct ActiveSenders<InstantType: InstantLike = Instant> {
-    slots: Slots,
-
+/// CUBIC-style congestion controller constants, borrowed from TCP CUBIC as
+/// used in neqo-transport's `cc/cubic.rs`.
+const CUBIC_BETA: f64 = 0.7;
+const CUBIC_C: f64 = 0.4;
+
+/// Floor for the allowed reconnection rate `W`, so that the recommended
+/// backoff never divides by (close to) zero.
+const MIN_ALLOWED_RATE: f64 = 0.001;
+
+/// Upper bound on the recommended backoff returned by
+/// `qdb_active_senders_track_established_v2`.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Default time-to-live for a slot: once this long has passed since the
+/// `track_established` call that allocated a slot, and `track_closed` has
+/// not released it, it's assumed leaked (e.g. the owning process crashed
+/// without calling `track_closed`) and is reclaimed by the next sweep. A
+/// slot's activity timestamp is only set when it is allocated, so a slot
+/// still in use past this age is reclaimed as well.
+const DEFAULT_SLOT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The default destination bucket used by the unkeyed `track_established`/
+/// `track_established_v2` entry points, for backward compatibility with
+/// callers that don't distinguish destinations.
+const DEFAULT_DEST_KEY: &[u8] = b"";
+
+/// Per-destination reconnection tracking state: an independent series map,
+/// warning cooldown and CUBIC controller per destination key (host:port, or
+/// an opaque connection-id token), so that one flapping endpoint doesn't
+/// blame or throttle unrelated healthy connections.
+struct DestState<InstantType: InstantLike> {
     /// Tracked established connection events.
     /// Keys are slot IDs, which are always non-negative integers.
     /// Values are `VecDeque<u64>` containing established connection `Instant` timestamps.
@@ -108,6 +144,52 @@ struct ActiveSenders<InstantType: InstantLike = Instant> {
     /// Timestamp of last issued warning
     last_warning: Option<InstantType>,
 
+    /// Timestamp of the most recent `track_established` call, used to
+    /// compute how much of the recommended backoff has already elapsed.
+    last_established_at: Option<InstantType>,
+
+    /// CUBIC-style allowed reconnection rate (reconnections per
+    /// `reconnect_warn_window`). Grows back towards and beyond `w_max`
+    /// after a congestion event, and is multiplicatively reduced on one.
+    w: f64,
+
+    /// CUBIC `w_max`: the allowed rate at the time of the last congestion
+    /// event.
+    w_max: f64,
+
+    /// Timestamp of the last congestion event (a crossing of
+    /// `reconnect_warn_threshold`), used as the CUBIC time origin.
+    last_congestion: Option<InstantType>,
+
+    /// Whether `max_recent_reconnections` was at/above
+    /// `reconnect_warn_threshold` as of the last `track_established_v2`
+    /// call, so that a multiplicative decrease is only applied on the
+    /// rising edge of a congestion event rather than on every call while
+    /// the rate stays elevated.
+    congested: bool,
+}
+
+impl<I: InstantLike> DestState<I> {
+    fn new(reconnect_warn_threshold: usize) -> Self {
+        Self {
+            series: std::collections::HashMap::new(),
+            last_warning: None,
+            last_established_at: None,
+            w: reconnect_warn_threshold as f64,
+            w_max: reconnect_warn_threshold as f64,
+            last_congestion: None,
+            congested: false,
+        }
+    }
+}
+
+struct ActiveSenders<InstantType: InstantLike = Instant> {
+    slots: Slots,
+
+    /// Independent tracking state per destination key. The unkeyed entry
+    /// points route to `DEFAULT_DEST_KEY`.
+    per_dest: std::collections::HashMap<Vec<u8>, DestState<InstantType>>,
+
     /// Window for counting recent reconnections.
     reconnect_warn_window: Duration,
 
@@ -116,17 +198,24 @@ struct ActiveSenders<InstantType: InstantLike = Instant> {
 
     /// Window to suppress warnings after the last warning.
     quiet_window: Duration,
+
+    /// Timestamp of the last `track_established` call for each live slot,
+    /// used by `sweep` to reclaim slots that were never closed.
+    last_activity: std::collections::HashMap<Slot, InstantType>,
+
+    /// A slot whose `last_activity` is older than this is assumed leaked
+    /// and is reclaimed on the next sweep.
+    slot_ttl: Duration,
 }
 
 #[cfg(test)]
 impl<I: InstantLike> Debug for ActiveSenders<I> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut series = self.series.iter().collect::<Vec<_>>();
-        series.sort_by(|(k1, _v1), (k2, _v2)| k1.cmp(k2));
+        let mut dests = self.per_dest.keys().collect::<Vec<_>>();
+        dests.sort_unstable();
         f.debug_struct("ActiveSenders")
             .field("slots", &self.slots)
-            .field("series", &series)
-            .field("last_warning", &self.last_warning)
+            .field("destinations", &dests)
             .finish()
     }
 }
@@ -136,24 +225,71 @@ impl<I: InstantLike<Output = I>> ActiveSenders<I> {
         reconnect_warn_window: Duration,
         reconnect_warn_threshold: usize,
         quiet_window: Duration,
+    ) -> Self {
+        Self::new_with_slot_ttl(
+            reconnect_warn_window,
+            reconnect_warn_threshold,
+            quiet_window,
+            DEFAULT_SLOT_TTL,
+        )
+    }
+
+    fn new_with_slot_ttl(
+        reconnect_warn_window: Duration,
+        reconnect_warn_threshold: usize,
+        quiet_window: Duration,
+        slot_ttl: Duration,
     ) -> Self {
         Self {
             slots: Slots::new(),
-            series: std::collections::HashMap::new(),
-            last_warning: None,
+            per_dest: std::collections::HashMap::new(),
             reconnect_warn_window,
             reconnect_warn_threshold,
             quiet_window,
+            last_activity: std::collections::HashMap::new(),
+            slot_ttl,
+        }
+    }
+
+    /// Reclaim any slot whose `last_activity` is older than `slot_ttl`: drop
+    /// its series from whichever destination it belonged to and return its
+    /// ID, so a leaked slot (e.g. the owning process crashed without
+    /// calling `track_closed`) doesn't skew `count_recent_reconnections`
+    /// forever. Deliberately does NOT restore the ID to `self.slots`: the
+    /// async path allocates its slot IDs from a separate
+    /// `AtomicSlotAllocator` (`ASYNC_SLOTS`) rather than `self.slots`, so
+    /// restoring here would either corrupt `self.slots` (still at its
+    /// initial, never-allocated-from state, in the async worker's private
+    /// `ActiveSenders`) or leak the ID from the allocator that actually
+    /// owns it. Each caller restores the returned IDs to whichever
+    /// allocator it uses: `track_established_inner` restores to
+    /// `self.slots`, `async_warn_worker` to `ASYNC_SLOTS`.
+    fn sweep(&mut self) -> Vec<Slot> {
+        let now = I::now();
+        let expired: Vec<Slot> = self
+            .last_activity
+            .iter()
+            .filter(|(_, &last)| now.duration_since(last) > self.slot_ttl)
+            .map(|(&slot_id, _)| slot_id)
+            .collect();
+
+        for &slot_id in &expired {
+            self.last_activity.remove(&slot_id);
+            for dest in self.per_dest.values_mut() {
+                dest.series.remove(&slot_id);
+            }
         }
+        expired
     }
 
-    fn count_recent_reconnections(&mut self) -> usize {
+    fn count_recent_reconnections(&mut self, key: &[u8]) -> usize {
         let now = I::now();
         let cutoff: I = now - self.reconnect_warn_window;
+        let dest = self.per_dest.get_mut(key).unwrap();
         let mut max_count = 0;
         let mut to_delete = Vec::new();
 
-        for (&slot_id, serie) in &mut self.series {
+        for (&slot_id, serie) in &mut dest.series {
             while let Some(&established) = serie.front() {
                 if established < cutoff {
                     serie.pop_front();
@@ -170,37 +306,130 @@ impl<I: InstantLike<Output = I>> ActiveSenders<I> {
         }
 
         for slot_id in to_delete {
-            self.series.remove(&slot_id);
+            dest.series.remove(&slot_id);
         }
 
         max_count
     }
 
-    fn track_established(&mut self) -> (Slot, bool) {
+    /// Core of `track_established`/`track_established_keyed`, additionally
+    /// returning the `max_recent_reconnections` count so that
+    /// `track_established_v2` can drive its CUBIC controller off the raw
+    /// count rather than the quiet-window-suppressed `warning` flag.
+    fn track_established_inner(&mut self, key: &[u8]) -> (Slot, usize, bool) {
+        for expired in self.sweep() {
+            self.slots.restore(expired);
+        }
+
         let slot_id = self.slots.next();
-        let serie = self
+        let (max_recent_reconnections, warning) = self.record_established(key, slot_id);
+        (slot_id, max_recent_reconnections, warning)
+    }
+
+    /// Record an established connection against `key` for an already
+    /// allocated `slot_id`, returning the current `max_recent_reconnections`
+    /// count and whether a warning should fire. Split out from
+    /// `track_established_inner` so the async worker (which allocates slots
+    /// itself via `AtomicSlotAllocator` rather than `self.slots`) can share
+    /// the same bookkeeping.
+    fn record_established(&mut self, key: &[u8], slot_id: Slot) -> (usize, bool) {
+        let now = I::now();
+        self.last_activity.insert(slot_id, now);
+        let reconnect_warn_threshold = self.reconnect_warn_threshold;
+        let dest = self
+            .per_dest
+            .entry(key.to_vec())
+            .or_insert_with(|| DestState::new(reconnect_warn_threshold));
+        let serie = dest
             .series
             .entry(slot_id)
-            .or_insert_with(|| VecDeque::with_capacity(2 * self.reconnect_warn_threshold));
-        serie.push_back(I::now());
+            .or_insert_with(|| VecDeque::with_capacity(2 * reconnect_warn_threshold));
+        serie.push_back(now);
 
-        let max_recent_reconnections = self.count_recent_reconnections();
+        let max_recent_reconnections = self.count_recent_reconnections(key);
+        let dest = self.per_dest.get_mut(key).unwrap();
 
         let mut warning = false;
 
         if max_recent_reconnections >= self.reconnect_warn_threshold {
             let now = I::now();
-            if self.last_warning.is_none()
-                || now.duration_since(self.last_warning.unwrap()) > self.quiet_window
+            if dest.last_warning.is_none()
+                || now.duration_since(dest.last_warning.unwrap()) > self.quiet_window
             {
                 warning = true;
-                self.last_warning = Some(now);
+                dest.last_warning = Some(now);
             }
         }
+        (max_recent_reconnections, warning)
+    }
+
+    fn track_established(&mut self) -> (Slot, bool) {
+        self.track_established_keyed(DEFAULT_DEST_KEY)
+    }
+
+    fn track_established_keyed(&mut self, key: &[u8]) -> (Slot, bool) {
+        let (slot_id, _max_recent_reconnections, warning) = self.track_established_inner(key);
         (slot_id, warning)
     }
 
+    /// Like `track_established`, but additionally recommends how long the
+    /// caller should sleep before its next reconnect attempt, computed with
+    /// a CUBIC-style controller over the allowed reconnection rate `W`
+    /// (reconnections per `reconnect_warn_window`).
+    fn track_established_v2(&mut self) -> (Slot, Duration, bool) {
+        let key = DEFAULT_DEST_KEY;
+        let now = I::now();
+        let elapsed_since_last = self
+            .per_dest
+            .get(key)
+            .and_then(|dest| dest.last_established_at)
+            .map(|t| now.duration_since(t))
+            .unwrap_or(Duration::ZERO);
+
+        let (slot_id, max_recent_reconnections, warning) = self.track_established_inner(key);
+        let currently_congested = max_recent_reconnections >= self.reconnect_warn_threshold;
+
+        let dest = self.per_dest.get_mut(key).unwrap();
+        dest.last_established_at = Some(now);
+
+        if currently_congested && !dest.congested {
+            // Rising edge of a congestion event: remember the rate we'd
+            // reached and back off multiplicatively.
+            dest.w_max = dest.w;
+            dest.w = CUBIC_BETA * dest.w_max;
+            dest.last_congestion = Some(now);
+        } else {
+            // Grow back towards (and eventually past) `w_max` along the
+            // cubic curve. This also covers staying above the threshold for
+            // multiple calls in a row: only the rising edge re-triggers the
+            // multiplicative decrease.
+            match dest.last_congestion {
+                None => {
+                    // No congestion event has ever happened: stay at the
+                    // configured allowed rate instead of following the
+                    // recovery curve from an event that never occurred.
+                    dest.w = dest.w_max;
+                }
+                Some(lc) => {
+                    let t = now.duration_since(lc).as_secs_f64();
+                    let k = (dest.w_max * (1.0 - CUBIC_BETA) / CUBIC_C).cbrt();
+                    dest.w = CUBIC_C * (t - k).powi(3) + dest.w_max;
+                }
+            }
+        }
+        dest.congested = currently_congested;
+        dest.w = dest.w.max(MIN_ALLOWED_RATE);
+
+        let target_interval = self.reconnect_warn_window.div_f64(dest.w);
+        let backoff = target_interval
+            .saturating_sub(elapsed_since_last)
+            .min(MAX_BACKOFF);
+
+        (slot_id, backoff, warning)
+    }
+
     fn track_closed(&mut self, slot_id: Slot) {
+        self.last_activity.remove(&slot_id);
         self.slots.restore(slot_id);
     }
 }
@@ -215,20 +444,241 @@ static ACTIVE_SENDERS: LazyLock<Mutex<ActiveSenders>> = LazyLock::new(|| {
 
 #[no_mangle]
 pub extern "C" fn qdb_active_senders_track_established(warn: *mut c_int) -> Slot {
+    let mut backoff_ms = 0u64;
+    qdb_active_senders_track_established_v2(&mut backoff_ms, warn)
+}
+
+/// Like `qdb_active_senders_track_established`, but additionally recommends
+/// a backoff (in milliseconds) the caller should sleep before its next
+/// reconnect attempt, computed with a CUBIC-style congestion controller.
+#[no_mangle]
+pub extern "C" fn qdb_active_senders_track_established_v2(
+    backoff_ms: *mut u64,
+    warn: *mut c_int,
+) -> Slot {
     let mut active_senders = ACTIVE_SENDERS.lock().unwrap();
-    let (slot_id, warning) = active_senders.track_established();
+    let (slot_id, backoff, warning) = active_senders.track_established_v2();
     unsafe {
+        *backoff_ms = backoff.as_millis() as u64;
         *warn = warning as c_int;
     }
     slot_id
 }
 
+/// Like `qdb_active_senders_track_established`, but scopes the reconnection
+/// tracking, warning cooldown and future backoff recommendation to the
+/// destination identified by `key_ptr`/`key_len` (e.g. a `host:port` string,
+/// or an opaque connection-id token), rather than lumping every sender in
+/// the process into one set of series.
+///
+/// # Safety
+/// `key_ptr` must point to `key_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_active_senders_track_established_keyed(
+    key_ptr: *const u8,
+    key_len: usize,
+    warn: *mut c_int,
+) -> Slot {
+    let key = std::slice::from_raw_parts(key_ptr, key_len);
+    let mut active_senders = ACTIVE_SENDERS.lock().unwrap();
+    let (slot_id, warning) = active_senders.track_established_keyed(key);
+    *warn = warning as c_int;
+    slot_id
+}
+
 #[no_mangle]
 pub extern "C" fn qdb_active_senders_track_closed(slot_id: Slot) {
     let mut active_senders = ACTIVE_SENDERS.lock().unwrap();
     active_senders.track_closed(slot_id);
 }
 
+/// The slot allocator used by the async mode below, separate from
+/// `ACTIVE_SENDERS`: `next` takes the `returned` mutex on every call to look
+/// for a reclaimed ID, and only when there is none falls back to an atomic
+/// increment of `next_slot`. Allocation never takes the
+/// `Mutex<ActiveSenders>` lock, but it is not lock-free.
+struct AtomicSlotAllocator {
+    next_slot: AtomicU32,
+    returned: Mutex<VecDeque<Slot>>,
+}
+
+impl AtomicSlotAllocator {
+    fn new() -> Self {
+        Self {
+            next_slot: AtomicU32::new(0),
+            returned: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn next(&self) -> Slot {
+        if let Some(slot_id) = self.returned.lock().unwrap().pop_front() {
+            slot_id
+        } else {
+            self.next_slot.fetch_add(1, Ordering::Relaxed)
+        }
+    }
+
+    fn restore(&self, slot_id: Slot) {
+        self.returned.lock().unwrap().push_back(slot_id);
+    }
+}
+
+/// Signature of the user-registered callback invoked by the async worker
+/// when a destination's reconnect rate crosses `reconnect_warn_threshold`.
+/// `key_ptr`/`key_len` describe the destination key passed to
+/// `qdb_active_senders_track_established_async`, and `rate` is the number
+/// of reconnections observed for it in the trailing `reconnect_warn_window`.
+/// Only valid for the duration of the call.
+pub type QdbActiveSendersWarnCallback =
+    extern "C" fn(key_ptr: *const u8, key_len: usize, rate: f64, user_data: *mut c_void);
+
+/// Holds the registered warning callback. Wrapped so the raw `user_data`
+/// pointer can live behind the `Mutex` despite not being `Send` itself; the
+/// pointer is opaque to us and only ever handed back to the callback that
+/// originally supplied it.
+struct WarnCallbackSlot {
+    cb: Option<QdbActiveSendersWarnCallback>,
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for WarnCallbackSlot {}
+
+static WARN_CALLBACK: Mutex<WarnCallbackSlot> = Mutex::new(WarnCallbackSlot {
+    cb: None,
+    user_data: std::ptr::null_mut(),
+});
+
+/// Register the callback invoked by the async worker to deliver warnings
+/// out-of-band. Replaces any previously registered callback.
+#[no_mangle]
+pub extern "C" fn qdb_active_senders_set_warn_callback(
+    cb: QdbActiveSendersWarnCallback,
+    user_data: *mut c_void,
+) {
+    let mut slot = WARN_CALLBACK.lock().unwrap();
+    slot.cb = Some(cb);
+    slot.user_data = user_data;
+}
+
+enum AsyncEvent {
+    Established { key: Vec<u8>, slot_id: Slot },
+    Closed(Slot),
+}
+
+static ASYNC_SLOTS: LazyLock<AtomicSlotAllocator> = LazyLock::new(AtomicSlotAllocator::new);
+
+/// Sending end of the channel to the background worker. Lazily spawns the
+/// worker thread on first use, so processes that never opt into async mode
+/// pay nothing for it.
+static ASYNC_EVENTS: LazyLock<Sender<AsyncEvent>> = LazyLock::new(|| {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    thread::spawn(move || async_warn_worker(rx));
+    tx
+});
+
+/// Handles one `AsyncEvent` against `active_senders`. Extracted out of
+/// `async_warn_worker` so it can be driven directly in tests with
+/// `MockInstant` and a fake `restore_slot`/`warn` sink, without spinning a
+/// real thread/channel or touching the process-wide `ASYNC_SLOTS`
+/// allocator.
+///
+/// Mirrors `track_established_inner`: sweeps on every `Established` event
+/// so a connection that crashes without calling
+/// `qdb_active_senders_track_closed_async` still has its slot reclaimed
+/// once `slot_ttl` elapses, instead of only being visible to the unrelated
+/// global `ACTIVE_SENDERS` swept by `qdb_active_senders_sweep`. Any expired
+/// slot is handed to `restore_slot` rather than `active_senders.slots`,
+/// since async slot IDs come from a separate allocator (`ASYNC_SLOTS` in
+/// production) that `active_senders.slots` knows nothing about.
+fn handle_async_event<I: InstantLike<Output = I>>(
+    active_senders: &mut ActiveSenders<I>,
+    event: AsyncEvent,
+    mut restore_slot: impl FnMut(Slot),
+    mut warn: impl FnMut(&[u8], f64),
+) {
+    match event {
+        AsyncEvent::Established { key, slot_id } => {
+            for expired in active_senders.sweep() {
+                restore_slot(expired);
+            }
+            let (rate, warning) = active_senders.record_established(&key, slot_id);
+            if warning {
+                warn(&key, rate as f64);
+            }
+        }
+        AsyncEvent::Closed(slot_id) => {
+            active_senders.last_activity.remove(&slot_id);
+        }
+    }
+}
+
+/// Owns its own `ActiveSenders`, single-threaded, so it never contends with
+/// the FFI callers: they only enqueue. Batches the window scans that would
+/// otherwise run inline on every `track_established` call.
+fn async_warn_worker(events: Receiver<AsyncEvent>) {
+    let mut active_senders = ActiveSenders::<Instant>::new(
+        Duration::from_secs(5),
+        25, // reconnections
+        Duration::from_secs(10 * 60),
+    );
+    for event in events {
+        handle_async_event(
+            &mut active_senders,
+            event,
+            |slot_id| ASYNC_SLOTS.restore(slot_id),
+            |key, rate| {
+                let callback = WARN_CALLBACK.lock().unwrap();
+                if let Some(cb) = callback.cb {
+                    cb(key.as_ptr(), key.len(), rate, callback.user_data);
+                }
+            },
+        );
+    }
+}
+
+/// Opt-in async counterpart to `qdb_active_senders_track_established_keyed`.
+/// Allocates a slot from `ASYNC_SLOTS` (see `AtomicSlotAllocator`) and hands the
+/// event off to a background worker rather than scanning reconnection
+/// windows inline, so multi-threaded clients don't serialize on connect.
+/// Warnings are delivered out-of-band through the callback registered with
+/// `qdb_active_senders_set_warn_callback` rather than an out-parameter.
+///
+/// # Safety
+/// `key_ptr` must point to `key_len` readable bytes; the key is copied
+/// before this function returns, so it need not outlive the call.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_active_senders_track_established_async(
+    key_ptr: *const u8,
+    key_len: usize,
+) -> Slot {
+    let key = std::slice::from_raw_parts(key_ptr, key_len).to_vec();
+    let slot_id = ASYNC_SLOTS.next();
+    let _ = ASYNC_EVENTS.send(AsyncEvent::Established { key, slot_id });
+    slot_id
+}
+
+/// Async counterpart to `qdb_active_senders_track_closed`. Must be used to
+/// close slots obtained from `qdb_active_senders_track_established_async`
+/// (the two slot pools are independent).
+#[no_mangle]
+pub extern "C" fn qdb_active_senders_track_closed_async(slot_id: Slot) {
+    ASYNC_SLOTS.restore(slot_id);
+    let _ = ASYNC_EVENTS.send(AsyncEvent::Closed(slot_id));
+}
+
+/// Reclaim any slot whose `last_activity` is older than the configured
+/// `slot_ttl`, e.g. after a sender process crashed without calling
+/// `qdb_active_senders_track_closed`. `track_established` already sweeps on
+/// every call; this is for callers that want to age out leaked slots
+/// without waiting for the next reconnection.
+#[no_mangle]
+pub extern "C" fn qdb_active_senders_sweep() {
+    let mut active_senders = ACTIVE_SENDERS.lock().unwrap();
+    for slot_id in active_senders.sweep() {
+        active_senders.slots.restore(slot_id);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cell::Cell;
@@ -455,6 +905,43 @@ mod tests {
         assert_eq!(active_senders.track_established(), (0, true)); // warn, 3rd reconnect within 5s
     }
 
+    #[test]
+    fn test_track_established_v2_backs_off_after_congestion_and_recovers() {
+        reset_mock_instant();
+        let mut active_senders =
+            ActiveSenders::<MockInstant>::new(Duration::from_secs(5), 3, Duration::from_secs(60));
+
+        // Three fast reconnects of the same logical connection trip the
+        // congestion event and the warning.
+        let (slot0, _backoff0, warn0) = active_senders.track_established_v2();
+        assert!(!warn0);
+        active_senders.track_closed(slot0);
+
+        advance_mock_instant(Duration::from_millis(100));
+        let (slot1, _backoff1, warn1) = active_senders.track_established_v2();
+        assert!(!warn1);
+        active_senders.track_closed(slot1);
+
+        advance_mock_instant(Duration::from_millis(100));
+        let (slot2, _backoff2, warn2) = active_senders.track_established_v2();
+        assert!(warn2); // 3rd reconnect within 5s: congestion event
+        active_senders.track_closed(slot2);
+
+        // `W` was cut to `beta * w_max`, so the recommended backoff should
+        // now be strictly positive.
+        advance_mock_instant(Duration::from_millis(100));
+        let (slot3, backoff3, _warn3) = active_senders.track_established_v2();
+        assert!(backoff3 > Duration::ZERO);
+        active_senders.track_closed(slot3);
+
+        // As time passes without further congestion, `W` recovers along the
+        // cubic curve and the recommended backoff shrinks back down.
+        advance_mock_instant(Duration::from_secs(30));
+        let (_, backoff4, warn4) = active_senders.track_established_v2();
+        assert!(!warn4);
+        assert!(backoff4 < backoff3);
+    }
+
     #[test]
     fn test_active_senders_slow_reconnect() {
         reset_mock_instant();
@@ -473,4 +960,139 @@ mod tests {
             advance_mock_instant(active_senders.reconnect_warn_window);
         }
     }
+
+    #[test]
+    fn test_active_senders_keyed_isolation() {
+        reset_mock_instant();
+        let mut active_senders =
+            ActiveSenders::<MockInstant>::new(Duration::from_secs(5), 3, Duration::from_secs(60));
+
+        // `host_a` flaps fast enough to trip the warning...
+        assert_eq!(active_senders.track_established_keyed(b"host_a"), (0, false));
+        active_senders.track_closed(0);
+
+        advance_mock_instant(Duration::from_millis(100));
+        assert_eq!(active_senders.track_established_keyed(b"host_a"), (0, false));
+        active_senders.track_closed(0);
+
+        advance_mock_instant(Duration::from_millis(100));
+        assert_eq!(active_senders.track_established_keyed(b"host_a"), (0, true));
+        active_senders.track_closed(0);
+
+        // ...but `host_b` reconnecting around the same time is unaffected,
+        // since it's tracked independently.
+        advance_mock_instant(Duration::from_millis(100));
+        assert_eq!(active_senders.track_established_keyed(b"host_b"), (0, false));
+        active_senders.track_closed(0);
+
+        // The default (unkeyed) bucket is also independent of both.
+        assert_eq!(active_senders.track_established(), (0, false));
+    }
+
+    #[test]
+    fn test_active_senders_sweep_reclaims_leaked_slots() {
+        reset_mock_instant();
+        let mut active_senders = ActiveSenders::<MockInstant>::new_with_slot_ttl(
+            Duration::from_secs(5),
+            3,
+            Duration::from_secs(60),
+            Duration::from_secs(30),
+        );
+
+        // Slot 0 is "leaked": established once and never closed.
+        assert_eq!(active_senders.track_established(), (0, false));
+
+        // Within the TTL, the slot is still alive: a new connection gets a
+        // fresh ID and the leaked slot's stale entry is still tracked.
+        advance_mock_instant(Duration::from_secs(10));
+        assert_eq!(active_senders.track_established(), (1, false));
+        active_senders.track_closed(1);
+        assert!(active_senders.last_activity.contains_key(&0));
+
+        // Once the leaked slot's `last_activity` is older than `slot_ttl`,
+        // `sweep` reclaims it: the ID is returned to the pool and its stale
+        // series no longer counts towards `count_recent_reconnections`.
+        advance_mock_instant(Duration::from_secs(30));
+        for slot_id in active_senders.sweep() {
+            active_senders.slots.restore(slot_id);
+        }
+        assert!(!active_senders.last_activity.contains_key(&0));
+        assert!(!active_senders.per_dest[DEFAULT_DEST_KEY]
+            .series
+            .contains_key(&0));
+
+        // The reclaimed ID is handed out again rather than growing the
+        // linear slot range unbounded.
+        assert_eq!(active_senders.track_established(), (0, false));
+    }
+
+    #[test]
+    fn test_active_senders_explicit_sweep() {
+        reset_mock_instant();
+        let mut active_senders = ActiveSenders::<MockInstant>::new_with_slot_ttl(
+            Duration::from_secs(5),
+            3,
+            Duration::from_secs(60),
+            Duration::from_secs(30),
+        );
+
+        assert_eq!(active_senders.track_established(), (0, false));
+        advance_mock_instant(Duration::from_secs(31));
+
+        for slot_id in active_senders.sweep() {
+            active_senders.slots.restore(slot_id);
+        }
+
+        assert!(active_senders.last_activity.is_empty());
+        assert_eq!(active_senders.track_established(), (0, false));
+    }
+
+    #[test]
+    fn test_handle_async_event_reclaims_expired_slot_via_restore_callback() {
+        reset_mock_instant();
+        let mut active_senders = ActiveSenders::<MockInstant>::new_with_slot_ttl(
+            Duration::from_secs(5),
+            3,
+            Duration::from_secs(60),
+            Duration::from_secs(30),
+        );
+
+        // Async slot IDs don't come from `active_senders.slots` (they come
+        // from the separate `ASYNC_SLOTS` allocator in production), so we
+        // hand out an ID here that `self.slots` never allocated, exactly as
+        // `qdb_active_senders_track_established_async` would.
+        let leaked_slot: Slot = 7;
+        let mut restored = Vec::new();
+        let mut warnings = Vec::new();
+        handle_async_event(
+            &mut active_senders,
+            AsyncEvent::Established {
+                key: b"host_a".to_vec(),
+                slot_id: leaked_slot,
+            },
+            |slot_id| restored.push(slot_id),
+            |key, rate| warnings.push((key.to_vec(), rate)),
+        );
+        assert!(restored.is_empty());
+        assert!(active_senders.last_activity.contains_key(&leaked_slot));
+
+        // Once the slot is older than `slot_ttl`, the next event must sweep
+        // it: no panic (the old bug underflowed `Slots::restore` here), and
+        // the expired ID is reported through `restore_slot`, not pushed into
+        // `active_senders.slots`.
+        advance_mock_instant(Duration::from_secs(31));
+        handle_async_event(
+            &mut active_senders,
+            AsyncEvent::Established {
+                key: b"host_b".to_vec(),
+                slot_id: 8,
+            },
+            |slot_id| restored.push(slot_id),
+            |key, rate| warnings.push((key.to_vec(), rate)),
+        );
+
+        assert_eq!(restored, vec![leaked_slot]);
+        assert!(!active_senders.last_activity.contains_key(&leaked_slot));
+        assert!(active_senders.last_activity.contains_key(&8));
+    }
 }
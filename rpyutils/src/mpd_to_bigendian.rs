@@ -47,6 +47,100 @@ pub unsafe extern "C" fn qdb_mpd_to_bigendian(
     }
 }
 
+/// Like `qdb_mpd_to_bigendian`, but rescales from the Decimal's own scale
+/// (`decimal_exp`) to the destination column's declared `target_scale`
+/// instead of only ever multiplying by a fixed power of ten, rounding with
+/// `rounding` when the Decimal carries more fractional digits than
+/// `target_scale` allows.
+///
+/// # Safety
+///
+/// Same requirements as `qdb_mpd_to_bigendian`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_mpd_to_bigendian_scaled(
+    limbs: *const usize,
+    limbs_len: usize,
+    radix: usize,
+    decimal_exp: i32,
+    target_scale: i32,
+    rounding: qdb_mpd_rounding_mode,
+    negative: bool,
+    out: *mut u8,
+    out_size: *mut usize,
+) -> bool {
+    let limbs = if limbs.is_null() {
+        return false;
+    } else {
+        unsafe { slice::from_raw_parts(limbs, limbs_len) }
+    };
+    let out = unsafe { slice::from_raw_parts_mut(out, 32) };
+    match mpd_to_bigendian_scaled(
+        limbs,
+        radix,
+        decimal_exp,
+        target_scale,
+        rounding,
+        negative,
+        out,
+    ) {
+        Some(size) => {
+            *out_size = size;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Converts a big-endian two's complement decimal column value back into
+/// little-endian mpdecimal limbs, the inverse of `qdb_mpd_to_bigendian`.
+///
+/// `bytes` is the (possibly sign-extension-trimmed) two's complement
+/// encoding produced by `qdb_mpd_to_bigendian`/`qdb_mpd_to_bigendian_scaled`,
+/// 1 to 32 bytes long, most significant byte first. `scale` is the column's
+/// declared scale; the reconstructed Decimal's exponent is always `-scale`.
+///
+/// On success, the first `*out_limbs_len` entries of `out_limbs` (which must
+/// have room for `out_limbs_cap` limbs) hold the little-endian `mpd_uint_t`
+/// limbs, `out_negative` holds the sign, and `out_exp` holds `-scale`. A
+/// `false` result means `bytes` was empty or longer than 32 bytes, or
+/// `out_limbs_cap` was too small to hold every limb.
+///
+/// # Safety
+///
+/// * `bytes` must point to `bytes_len` valid bytes.
+/// * `out_limbs` must point to `out_limbs_cap` valid, writable `mpd_uint_t`
+///   limbs.
+/// * `out_limbs_len`, `out_negative` and `out_exp` must point to writable
+///   memory.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_bigendian_to_mpd(
+    bytes: *const u8,
+    bytes_len: usize,
+    radix: usize,
+    scale: i32,
+    out_limbs: *mut usize,
+    out_limbs_cap: usize,
+    out_limbs_len: *mut usize,
+    out_negative: *mut bool,
+    out_exp: *mut i32,
+) -> bool {
+    let bytes = if bytes.is_null() {
+        return false;
+    } else {
+        unsafe { slice::from_raw_parts(bytes, bytes_len) }
+    };
+    let out_limbs = unsafe { slice::from_raw_parts_mut(out_limbs, out_limbs_cap) };
+    match bigendian_to_mpd(bytes, radix, out_limbs) {
+        Some((len, negative)) => {
+            *out_limbs_len = len;
+            *out_negative = negative;
+            *out_exp = -scale;
+            true
+        }
+        None => false,
+    }
+}
+
 fn reduce_limbs(limbs: &[usize], radix: usize) -> Option<i256::i256> {
     let mut value = i256::i256::from(0);
     for limb in limbs.iter().rev() {
@@ -108,6 +202,139 @@ fn mpd_to_bigendian(
     Some(write_trimmed_bytes(value, negative, out))
 }
 
+/// Converts `bytes`, a big-endian two's complement encoding of 1 to 32
+/// bytes, back into little-endian mpdecimal limbs using `radix`.
+///
+/// Returns `Some((limb_count, negative))` on success, with the first
+/// `limb_count` entries of `out_limbs` populated least-significant-first. A
+/// `None` result means `bytes` was empty or longer than 32 bytes, or
+/// `out_limbs` was too small to hold every limb.
+///
+/// Mirrors `reduce_limbs` in reverse: that function builds a value that is
+/// always non-positive (`-magnitude`) by repeated multiply-then-subtract
+/// using truncating arithmetic, so this walks the same accumulator back
+/// down by repeated truncating division, which yields non-positive
+/// remainders whose negation is a valid limb. Starting from `value`
+/// directly, rather than negating it first, when the decimal is already
+/// negative sidesteps the one input, `i256::MIN`, that cannot be negated
+/// without overflowing.
+fn bigendian_to_mpd(bytes: &[u8], radix: usize, out_limbs: &mut [usize]) -> Option<(usize, bool)> {
+    if bytes.is_empty() || bytes.len() > 32 {
+        return None;
+    }
+    let negative = bytes[0] & 0x80 != 0;
+    let fill = if negative { 0xFFu8 } else { 0x00u8 };
+    let mut full = [fill; 32];
+    let start = 32 - bytes.len();
+    full[start..].copy_from_slice(bytes);
+    let value = i256::i256::from_be_bytes(full);
+
+    let mut magnitude = if negative { value } else { value.checked_neg()? };
+    let zero = i256::i256::from(0);
+    let radix = i256::i256::from(radix as u64);
+    let mut len = 0;
+    while magnitude != zero {
+        if len >= out_limbs.len() {
+            return None;
+        }
+        let (q, r) = magnitude.checked_div_rem(radix)?;
+        out_limbs[len] = r.checked_neg()?.as_u128() as usize;
+        len += 1;
+        magnitude = q;
+    }
+    Some((len, negative))
+}
+
+/// Selects how `mpd_to_bigendian_scaled` rounds a value whose scale exceeds
+/// the destination column's declared scale.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum qdb_mpd_rounding_mode {
+    /// Round half to even (banker's rounding); mpdecimal's own default.
+    HalfEven = 0,
+    /// Round half away from zero.
+    HalfUp = 1,
+    /// Truncate towards zero.
+    Down = 2,
+    /// Round away from zero.
+    Up = 3,
+}
+
+/// Divide `value` by `divisor` (`divisor` must be positive), rounding the
+/// quotient according to `rounding`. Returns `None` on 256-bit overflow.
+fn round_div(
+    value: i256::i256,
+    divisor: i256::i256,
+    rounding: qdb_mpd_rounding_mode,
+) -> Option<i256::i256> {
+    let zero = i256::i256::from(0);
+    let (q, r) = value.checked_div_rem(divisor)?;
+    if r == zero {
+        return Some(q);
+    }
+    let r_abs = if r < zero { r.checked_neg()? } else { r };
+    let twice = r_abs.checked_mul(i256::i256::from(2))?;
+    let round_away_from_zero = match rounding {
+        qdb_mpd_rounding_mode::Down => false,
+        qdb_mpd_rounding_mode::Up => true,
+        qdb_mpd_rounding_mode::HalfUp => twice >= divisor,
+        qdb_mpd_rounding_mode::HalfEven => match twice.cmp(&divisor) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Equal => q.checked_rem(i256::i256::from(2))? != zero,
+            std::cmp::Ordering::Less => false,
+        },
+    };
+    if !round_away_from_zero {
+        return Some(q);
+    }
+    if value < zero {
+        q.checked_sub(i256::i256::from(1))
+    } else {
+        q.checked_add(i256::i256::from(1))
+    }
+}
+
+/// Converts the provided limbs to a 256-bit big-endian two's complement
+/// array, rescaling from the Decimal's own scale (`decimal_exp`, i.e. the
+/// number of digits to the right of the decimal point in its coefficient) to
+/// the destination column's declared `target_scale`, in the same convention.
+///
+/// When `target_scale >= decimal_exp` the reduced value is multiplied by
+/// `10^(target_scale - decimal_exp)`, same as `mpd_to_bigendian`. When
+/// `target_scale < decimal_exp` the Decimal carries more fractional digits
+/// than the column can hold, so the value is divided by
+/// `10^(decimal_exp - target_scale)` and rounded per `rounding` instead of
+/// erroring or silently truncating.
+///
+/// Returns `None` on 256-bit overflow, exactly as `mpd_to_bigendian` does.
+fn mpd_to_bigendian_scaled(
+    limbs: &[usize],
+    radix: usize,
+    decimal_exp: i32,
+    target_scale: i32,
+    rounding: qdb_mpd_rounding_mode,
+    negative: bool,
+    out: &mut [u8],
+) -> Option<usize> {
+    debug_assert!(out.len() == 32);
+
+    let mut value = reduce_limbs(limbs, radix)?;
+    if !negative {
+        value = value.checked_neg()?
+    }
+    let shift = target_scale.checked_sub(decimal_exp)?;
+    if shift >= 0 {
+        let pow10 = i256::i256::from(10).checked_pow(shift as u32)?;
+        value = value.checked_mul(pow10)?;
+    } else {
+        let magnitude = shift.checked_neg()? as u32;
+        let divisor = i256::i256::from(10).checked_pow(magnitude)?;
+        value = round_div(value, divisor, rounding)?;
+    }
+    Some(write_trimmed_bytes(value, negative, out))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -397,6 +624,185 @@ mod tests {
         assert!(!ok);
     }
 
+    #[test]
+    fn scaled_multiplies_when_target_scale_is_wider() {
+        // 1.23 (coefficient 123, scale 2) rescaled to scale 4 -> 12300.
+        let limbs = [123usize];
+        let mut out = [0xAAu8; 32];
+
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 2, 4, qdb_mpd_rounding_mode::HalfEven, false, &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(&out[..written], &[0x30, 0x0C]); // 12300
+        assert!(out[written..].iter().all(|b| *b == 0xAA));
+    }
+
+    #[test]
+    fn scaled_half_even_rounds_to_nearest_even() {
+        // 12.5 (coefficient 125, scale 1) rescaled to scale 0 is an exact
+        // halfway case: 12 is already even, so it stays at 12.
+        let limbs = [125usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::HalfEven, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0C]); // 12
+
+        // 13.5 (coefficient 135) is also an exact halfway case, but 13 is
+        // odd, so it rounds up to the even 14.
+        let limbs = [135usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::HalfEven, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0E]); // 14
+    }
+
+    #[test]
+    fn scaled_half_even_rounds_to_nearest_even_negative() {
+        // -12.5 stays at -12 (even); -13.5 rounds away to -14 (even).
+        let limbs = [125usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::HalfEven, true, &mut out,
+        )
+        .unwrap();
+        let decoded = decode_value(&out[..written], true);
+        assert_eq!(decoded, i256::i256::from(-12));
+
+        let limbs = [135usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::HalfEven, true, &mut out,
+        )
+        .unwrap();
+        let decoded = decode_value(&out[..written], true);
+        assert_eq!(decoded, i256::i256::from(-14));
+    }
+
+    #[test]
+    fn scaled_half_up_rounds_halfway_away_from_zero() {
+        // 13.5 rounds up to 14 under HalfUp, unlike plain Down truncation.
+        let limbs = [135usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::HalfUp, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0E]); // 14
+    }
+
+    #[test]
+    fn scaled_down_truncates_towards_zero() {
+        // 12.6 truncates to 12 under Down, even though it is closer to 13.
+        let limbs = [126usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::Down, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0C]); // 12
+    }
+
+    #[test]
+    fn scaled_up_rounds_any_remainder_away_from_zero() {
+        // 12.1 rounds up to 13 under Up, since any nonzero remainder rounds
+        // away from zero regardless of how close it is to the next integer.
+        let limbs = [121usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::Up, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0D]); // 13
+    }
+
+    #[test]
+    fn scaled_exact_division_needs_no_rounding() {
+        // 12.0 divides evenly to 12 regardless of rounding mode.
+        let limbs = [120usize];
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian_scaled(
+            &limbs, 10, 1, 0, qdb_mpd_rounding_mode::Down, false, &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..written], &[0x00, 0x0C]); // 12
+    }
+
+    #[test]
+    fn scaled_returns_none_on_multiply_overflow() {
+        let limbs = [2usize];
+        let mut out = [0u8; 32];
+
+        // Needs to multiply by 10^256, which does not fit in 256 bits.
+        let r = mpd_to_bigendian_scaled(
+            &limbs, 10, 0, 256, qdb_mpd_rounding_mode::HalfEven, false, &mut out,
+        );
+        assert!(r.is_none());
+    }
+
+    #[test]
+    fn scaled_returns_none_on_divisor_overflow() {
+        let limbs = [2usize];
+        let mut out = [0u8; 32];
+
+        // Needs to divide by 10^256, which does not fit in 256 bits either.
+        let r = mpd_to_bigendian_scaled(
+            &limbs, 10, 256, 0, qdb_mpd_rounding_mode::HalfEven, false, &mut out,
+        );
+        assert!(r.is_none());
+    }
+
+    #[test]
+    fn ffi_scaled_wrapper_marshals_arguments() {
+        let limbs = vec![135usize];
+        let mut out = [0xAAu8; 32];
+        let mut out_size = 0usize;
+
+        let ok = unsafe {
+            qdb_mpd_to_bigendian_scaled(
+                limbs.as_ptr(),
+                limbs.len(),
+                10,
+                1,
+                0,
+                qdb_mpd_rounding_mode::HalfEven,
+                false,
+                out.as_mut_ptr(),
+                &mut out_size,
+            )
+        };
+
+        assert!(ok);
+        assert_eq!(&out[..out_size], &[0x00, 0x0E]); // 13.5 -> 14
+        assert!(out[out_size..].iter().all(|b| *b == 0xAA));
+    }
+
+    #[test]
+    fn ffi_scaled_wrapper_rejects_null_limbs() {
+        let mut out = [0u8; 32];
+        let mut out_size = 0usize;
+        let ok = unsafe {
+            qdb_mpd_to_bigendian_scaled(
+                std::ptr::null_mut(),
+                0,
+                10,
+                0,
+                0,
+                qdb_mpd_rounding_mode::HalfEven,
+                false,
+                out.as_mut_ptr(),
+                &mut out_size,
+            )
+        };
+
+        assert!(!ok);
+    }
+
     #[test]
     fn ffi_wrapper_rejects_null_limbs() {
         let mut out = [0u8; 32];
@@ -415,4 +821,142 @@ mod tests {
 
         assert!(!ok);
     }
+
+    #[test]
+    fn bigendian_to_mpd_round_trips_positive() {
+        let limbs = [123_456_789usize, 987_654_321usize, 202_122_212usize];
+        let radix = 1_000_000_000usize;
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian(&limbs, radix, 0, false, &mut out).unwrap();
+
+        let mut round_tripped = [0usize; 8];
+        let (len, negative) = bigendian_to_mpd(&out[..written], radix, &mut round_tripped).unwrap();
+
+        assert!(!negative);
+        assert_eq!(&round_tripped[..len], &limbs);
+    }
+
+    #[test]
+    fn bigendian_to_mpd_round_trips_negative() {
+        let limbs = [0x0123usize, 0x4567usize, 0x89ABusize];
+        let radix = 1usize << 16;
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian(&limbs, radix, 0, true, &mut out).unwrap();
+
+        let mut round_tripped = [0usize; 8];
+        let (len, negative) = bigendian_to_mpd(&out[..written], radix, &mut round_tripped).unwrap();
+
+        assert!(negative);
+        assert_eq!(&round_tripped[..len], &limbs);
+    }
+
+    #[test]
+    fn bigendian_to_mpd_handles_zero() {
+        let out = [0u8; 32];
+        let mut round_tripped = [0usize; 8];
+        let (len, negative) = bigendian_to_mpd(&out, 10, &mut round_tripped).unwrap();
+
+        assert_eq!(len, 0);
+        assert!(!negative);
+
+        // A single zero byte (shorter than the two bytes `mpd_to_bigendian`
+        // writes for zero) also decodes to zero.
+        let (len, negative) = bigendian_to_mpd(&out[..1], 10, &mut round_tripped).unwrap();
+        assert_eq!(len, 0);
+        assert!(!negative);
+    }
+
+    #[test]
+    fn bigendian_to_mpd_round_trips_extreme_values() {
+        // decimal representation of -2²⁵⁵, the one value whose magnitude
+        // cannot be represented as a positive i256 (the i256::MIN case).
+        let limbs = [
+            8792003956564819968usize,
+            3499233282028201972usize,
+            7854925043439539266usize,
+            7896044618658097711usize,
+            5usize,
+        ];
+        let radix = 10000000000000000000usize;
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian(&limbs, radix, 0, true, &mut out).unwrap();
+
+        let mut round_tripped = [0usize; 8];
+        let (len, negative) = bigendian_to_mpd(&out[..written], radix, &mut round_tripped).unwrap();
+
+        assert!(negative);
+        assert_eq!(&round_tripped[..len], &limbs);
+    }
+
+    #[test]
+    fn bigendian_to_mpd_rejects_empty_and_oversized_input() {
+        let mut out = [0usize; 8];
+        assert!(bigendian_to_mpd(&[], 10, &mut out).is_none());
+        assert!(bigendian_to_mpd(&[0u8; 33], 10, &mut out).is_none());
+    }
+
+    #[test]
+    fn bigendian_to_mpd_rejects_undersized_limb_buffer() {
+        let limbs = [123_456_789usize, 987_654_321usize, 202_122_212usize];
+        let radix = 1_000_000_000usize;
+        let mut out = [0xAAu8; 32];
+        let written = mpd_to_bigendian(&limbs, radix, 0, false, &mut out).unwrap();
+
+        let mut round_tripped = [0usize; 2];
+        assert!(bigendian_to_mpd(&out[..written], radix, &mut round_tripped).is_none());
+    }
+
+    #[test]
+    fn ffi_bigendian_to_mpd_marshals_arguments() {
+        let limbs = [123_456_789usize, 987_654_321usize];
+        let radix = 1_000_000_000usize;
+        let mut bytes = [0xAAu8; 32];
+        let written = mpd_to_bigendian(&limbs, radix, 0, true, &mut bytes).unwrap();
+
+        let mut out_limbs = [0usize; 8];
+        let mut out_limbs_len = 0usize;
+        let mut out_negative = false;
+        let mut out_exp = 0i32;
+        let ok = unsafe {
+            qdb_bigendian_to_mpd(
+                bytes.as_ptr(),
+                written,
+                radix,
+                2,
+                out_limbs.as_mut_ptr(),
+                out_limbs.len(),
+                &mut out_limbs_len,
+                &mut out_negative,
+                &mut out_exp,
+            )
+        };
+
+        assert!(ok);
+        assert_eq!(&out_limbs[..out_limbs_len], &limbs);
+        assert!(out_negative);
+        assert_eq!(out_exp, -2);
+    }
+
+    #[test]
+    fn ffi_bigendian_to_mpd_rejects_null_bytes() {
+        let mut out_limbs = [0usize; 8];
+        let mut out_limbs_len = 0usize;
+        let mut out_negative = false;
+        let mut out_exp = 0i32;
+        let ok = unsafe {
+            qdb_bigendian_to_mpd(
+                std::ptr::null(),
+                0,
+                10,
+                0,
+                out_limbs.as_mut_ptr(),
+                out_limbs.len(),
+                &mut out_limbs_len,
+                &mut out_negative,
+                &mut out_exp,
+            )
+        };
+
+        assert!(!ok);
+    }
 }
@@ -22,11 +22,93 @@
  *
  ******************************************************************************/
 
-use std::ffi::c_char;
+use std::ffi::{c_char, c_void};
 use std::slice::from_raw_parts;
 
+/// Below this many bytes, a chain segment's backing store is a small, own
+/// heap box rather than a full `MIN_BUF_LEN` `String`: most ILP symbol and
+/// column names, and many string field values, are well under this size,
+/// and forcing a 1024-byte allocation for a two-byte tag wastes most of it.
+///
+/// The small storage is still its own heap allocation (not embedded
+/// directly in `Segment`/the chain `Vec`) because the chain can reallocate
+/// and move its elements when it grows: a pointer handed out to C for one
+/// segment must stay valid across later pushes, which only holds if the
+/// byte storage lives at a fixed address independent of where the
+/// `Segment` header describing it ends up.
+const SMALL_BUF_LEN: usize = 24;
+
+/// One link in the buffer chain: either a small boxed byte array (the
+/// common case for short field values) or a `String` for anything that
+/// doesn't fit. Exposes the same `len`/`capacity`/`truncate`/`clear` shape
+/// as `String` so callers don't need to know which storage backs a given
+/// segment.
+enum Segment {
+    Small(Box<[u8; SMALL_BUF_LEN]>, usize),
+    Large(String),
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        match self {
+            Segment::Small(_, len) => *len,
+            Segment::Large(s) => s.len(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Segment::Small(_, _) => SMALL_BUF_LEN,
+            Segment::Large(s) => s.capacity(),
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Segment::Small(buf, len) => &buf[..*len],
+            Segment::Large(s) => s.as_bytes(),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // SAFETY: only ever written to via `encode_loop` and friends, which
+        // only ever write valid UTF-8.
+        unsafe { std::str::from_utf8_unchecked(self.as_bytes()) }
+    }
+
+    fn truncate(&mut self, n: usize) {
+        match self {
+            Segment::Small(_, len) => *len = n,
+            Segment::Large(s) => s.truncate(n),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Raw pointer to the start of this segment's storage. Valid for
+    /// `self.capacity()` bytes.
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            Segment::Small(buf, _) => buf.as_mut_ptr(),
+            Segment::Large(s) => unsafe { s.as_mut_vec() }.as_mut_ptr(),
+        }
+    }
+
+    /// Mark `n` bytes of this segment's storage as initialized. Same
+    /// unsafe contract as `Vec::set_len`: the caller must have written
+    /// valid UTF-8 up to `n` bytes.
+    unsafe fn set_len(&mut self, n: usize) {
+        match self {
+            Segment::Small(_, len) => *len = n,
+            Segment::Large(s) => s.as_mut_vec().set_len(n),
+        }
+    }
+}
+
 #[allow(non_camel_case_types)]
-pub struct qdb_pystr_buf(Vec<String>);
+pub struct qdb_pystr_buf(Vec<Segment>);
 
 #[repr(C)]
 #[allow(non_camel_case_types)]
@@ -92,22 +174,30 @@ const MIN_BUF_LEN: usize = 1024;
 /// A carefully crafted buffer with spare capacity for `len` bytes.
 /// This is necessary to return "stable" addresses and avoid segfaults.
 /// Rust is unaware we are borrowing its memory and could try to free it as
-/// part of a reallocation if we were to use a `String` directly.
-fn get_dest(chain: &mut Vec<String>, len: usize) -> &mut String {
+/// part of a reallocation if we were to use a `String`/`Vec` directly.
+/// Picks a `Segment::Small` when `len` fits, else a `Segment::Large`.
+fn get_dest(chain: &mut Vec<Segment>, len: usize) -> &mut Segment {
     if !chain.is_empty() {
         let last = chain.last_mut().unwrap();
         if last.capacity() - last.len() >= len {
             return chain.last_mut().unwrap();
         }
     }
-    chain.push(String::with_capacity(std::cmp::max(len, MIN_BUF_LEN)));
+    if len <= SMALL_BUF_LEN {
+        chain.push(Segment::Small(Box::new([0u8; SMALL_BUF_LEN]), 0));
+    } else {
+        chain.push(Segment::Large(String::with_capacity(std::cmp::max(
+            len,
+            MIN_BUF_LEN,
+        ))));
+    }
     chain.last_mut().unwrap()
 }
 
 #[inline(always)]
 fn encode_loop<'a, T, F>(
     utf8_mult: usize,
-    chain: &'a mut Vec<String>,
+    chain: &'a mut Vec<Segment>,
     buf: &[T],
     get_char: F,
 ) -> Result<&'a str, u32>
@@ -117,67 +207,376 @@ where
 {
     let dest = get_dest(chain, utf8_mult * buf.len());
     let last = dest.len();
-    // for &b in buf.iter() {
-    //     // Checking for validity is not optional:
-    //     // >>> for n in range(2 ** 16):
-    //     // >>>     chr(n).encode('utf-8')
-    //     // UnicodeEncodeError: 'utf-8' codec can't encode character '\ud800'
-    //     //   in position 0: surrogates not allowed
-    //     match get_char(b) {
-    //         Some(c) => dest.push(c),
-    //         None => {
-    //             dest.truncate(last);
-    //             return Err(b.into());
-    //         }
-    //     }
-    // }
-    // Ok(&dest[last..])
-    unsafe {
-        let v = dest.as_mut_vec();
-        v.set_len(v.capacity());
-        let mut index = last;
+    let base = dest.as_mut_ptr();
+    let mut index = last;
 
+    unsafe {
         for &b in buf.iter() {
             let c = match get_char(b) {
                 Some(c) => c,
                 None => {
-                    v.set_len(last);
+                    dest.set_len(last);
                     return Err(b.into());
                 }
             };
-            let utf_c_len = c.len_utf8();
-            match utf_c_len {
-                1 => {
-                    v[index] = c as u8;
-                }
-                2 => {
+            let mut codepoint_buf = [0; 4];
+            let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), base.add(index), bytes.len());
+            index += bytes.len();
+        }
+        dest.set_len(index);
+    }
+    Ok(&dest.as_str()[last..])
+}
+
+/// Returns whether `cp` is a UTF-16 high (leading) surrogate.
+fn is_high_surrogate(cp: u32) -> bool {
+    (0xd800..=0xdbff).contains(&cp)
+}
+
+/// Returns whether `cp` is a UTF-16 low (trailing) surrogate.
+fn is_low_surrogate(cp: u32) -> bool {
+    (0xdc00..=0xdfff).contains(&cp)
+}
+
+/// Combine a high/low surrogate pair into the supplementary code point it
+/// represents.
+fn combine_surrogates(hi: u32, lo: u32) -> u32 {
+    0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+}
+
+/// Encode a lone (unpaired) surrogate `cp` (0xD800-0xDFFF) as the 3-byte
+/// sequence it would occupy as an ordinary UTF-8 code point, i.e. without
+/// the "no surrogates" validity check that `char` would otherwise enforce.
+/// This is the WTF-8 encoding of a lone surrogate:
+/// https://simonsapin.github.io/wtf-8/
+fn encode_lone_surrogate(cp: u32) -> [u8; 3] {
+    [
+        0xe0 | ((cp >> 12) & 0x0f) as u8,
+        0x80 | ((cp >> 6) & 0x3f) as u8,
+        0x80 | (cp & 0x3f) as u8,
+    ]
+}
+
+/// Write `bytes` at `base[index..]`, returning `index + bytes.len()`.
+unsafe fn write_bytes_at(base: *mut u8, index: usize, bytes: &[u8]) -> usize {
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), base.add(index), bytes.len());
+    index + bytes.len()
+}
+
+/// The UTF-8 encoding of U+FFFD REPLACEMENT CHARACTER.
+const REPLACEMENT_UTF8: [u8; 3] = [0xef, 0xbf, 0xbd];
+
+/// Encode every element of `buf` to UTF-8, substituting U+FFFD for any code
+/// point rejected by `get_char` instead of failing the whole conversion.
+/// Returns the encoded string together with the number of substitutions
+/// made. Since a substitution is always 3 bytes, `utf8_mult` is widened to
+/// at least 3 so the capacity reserved by `get_dest` covers every raw write
+/// even when replacing a 1-byte-per-unit (UCS1) source.
+#[inline(always)]
+fn encode_loop_lossy<'a, T, F>(
+    utf8_mult: usize,
+    chain: &'a mut Vec<Segment>,
+    buf: &[T],
+    get_char: F,
+) -> (&'a str, usize)
+where
+    F: Fn(T) -> Option<char>,
+    T: Copy,
+{
+    let utf8_mult = std::cmp::max(utf8_mult, 3);
+    let dest = get_dest(chain, utf8_mult * buf.len());
+    let last = dest.len();
+    let base = dest.as_mut_ptr();
+    let mut substitutions = 0usize;
+    let mut index = last;
+    unsafe {
+        for &b in buf.iter() {
+            index = match get_char(b) {
+                Some(c) => {
                     let mut codepoint_buf = [0; 4];
                     let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) = *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) = *bytes.get_unchecked(1);
+                    write_bytes_at(base, index, bytes)
                 }
-                3 => {
-                    let mut codepoint_buf = [0; 4];
-                    let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) = *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) = *bytes.get_unchecked(1);
-                    *v.get_unchecked_mut(index + 2) = *bytes.get_unchecked(2);
+                None => {
+                    substitutions += 1;
+                    write_bytes_at(base, index, &REPLACEMENT_UTF8)
+                }
+            };
+        }
+        dest.set_len(index);
+    }
+    (&dest.as_str()[last..], substitutions)
+}
+
+/// Like `encode_loop`, but in WTF-8 mode: a high surrogate immediately
+/// followed by a low surrogate is combined into the supplementary code
+/// point it represents and emitted as 4 bytes; any other lone surrogate is
+/// preserved as its own 3-byte sequence rather than rejected. Needs a
+/// one-element lookahead to detect pairs, including the edge case of a
+/// high surrogate as the final element of `buf`. Still rejects any code
+/// point above `0x10FFFF`, truncating the destination back to `last` and
+/// leaving the chain untouched, same as `encode_loop`.
+#[inline(always)]
+fn encode_loop_wtf8<'a, T>(
+    utf8_mult: usize,
+    chain: &'a mut Vec<Segment>,
+    buf: &[T],
+) -> Result<&'a str, u32>
+where
+    T: Copy + Into<u32>,
+{
+    let dest = get_dest(chain, utf8_mult * buf.len());
+    let last = dest.len();
+    let base = dest.as_mut_ptr();
+    let mut index = last;
+    unsafe {
+        let mut i = 0;
+        while i < buf.len() {
+            let cp: u32 = buf[i].into();
+            if is_high_surrogate(cp) {
+                if let Some(&next) = buf.get(i + 1) {
+                    let next_cp: u32 = next.into();
+                    if is_low_surrogate(next_cp) {
+                        let combined = combine_surrogates(cp, next_cp);
+                        let mut codepoint_buf = [0; 4];
+                        let bytes = char::from_u32(combined)
+                            .unwrap()
+                            .encode_utf8(&mut codepoint_buf)
+                            .as_bytes();
+                        index = write_bytes_at(base, index, bytes);
+                        i += 2;
+                        continue;
+                    }
                 }
-                4 => {
+                index = write_bytes_at(base, index, &encode_lone_surrogate(cp));
+                i += 1;
+                continue;
+            }
+            if is_low_surrogate(cp) {
+                index = write_bytes_at(base, index, &encode_lone_surrogate(cp));
+                i += 1;
+                continue;
+            }
+            match char::from_u32(cp) {
+                Some(c) => {
                     let mut codepoint_buf = [0; 4];
                     let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
-                    *v.get_unchecked_mut(index) = *bytes.get_unchecked(0);
-                    *v.get_unchecked_mut(index + 1) = *bytes.get_unchecked(1);
-                    *v.get_unchecked_mut(index + 2) = *bytes.get_unchecked(2);
-                    *v.get_unchecked_mut(index + 3) = *bytes.get_unchecked(3);
+                    index = write_bytes_at(base, index, bytes);
+                }
+                None => {
+                    dest.set_len(last);
+                    return Err(cp);
                 }
-                _ => unreachable!(),
             }
-            index += utf_c_len;
+            i += 1;
         }
-        v.set_len(index);
+        dest.set_len(index);
     }
-    Ok(&dest[last..])
+    Ok(&dest.as_str()[last..])
+}
+
+/// Convert a Py_UCS2 string to UTF-8 in WTF-8 mode: combines adjacent
+/// surrogate pairs into their supplementary code point, and preserves any
+/// other lone surrogate as its own 3-byte sequence rather than rejecting
+/// the whole conversion. Returns a `buf_out` borrowed ptr of `size_out`
+/// len. The buffer is borrowed from `b`.
+/// In case of errors (a code point that cannot be combined or preserved,
+/// i.e. none can occur for a Py_UCS2 input), returns `false` and
+/// `bad_codepoint_out` is set to the offending codepoint.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs2_to_wtf8(
+    b: *mut qdb_pystr_buf,
+    count: usize,
+    input: *const u16,
+    size_out: *mut usize,
+    buf_out: *mut *const c_char,
+    bad_codepoint_out: *mut u32,
+) -> bool {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    // Worst case is 3 bytes/unit: either a lone surrogate (3 bytes for 1
+    // unit) or a combined pair (4 bytes for 2 units).
+    let utf8_mult = 3;
+    let res = encode_loop_wtf8(utf8_mult, &mut b.0, i);
+    match res {
+        Ok(s) => {
+            *size_out = s.len();
+            *buf_out = s.as_ptr() as *const c_char;
+            true
+        }
+        Err(bad) => {
+            *bad_codepoint_out = bad;
+            false
+        }
+    }
+}
+
+/// Convert a Py_UCS4 string to UTF-8 in WTF-8 mode: combines adjacent
+/// surrogate pairs into their supplementary code point, and preserves any
+/// other lone surrogate as its own 3-byte sequence rather than rejecting
+/// the whole conversion. Returns a `buf_out` borrowed ptr of `size_out`
+/// len. The buffer is borrowed from `b`.
+/// In case of errors (a genuinely invalid code point, i.e. `> 0x10FFFF`),
+/// returns `false` and `bad_codepoint_out` is set to the offending
+/// codepoint.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs4_to_wtf8(
+    b: *mut qdb_pystr_buf,
+    count: usize,
+    input: *const u32,
+    size_out: *mut usize,
+    buf_out: *mut *const c_char,
+    bad_codepoint_out: *mut u32,
+) -> bool {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    // Max 4 bytes allowed by RFC: https://www.rfc-editor.org/rfc/rfc3629#page-4
+    let utf8_mult = 4;
+    let res = encode_loop_wtf8(utf8_mult, &mut b.0, i);
+    match res {
+        Ok(s) => {
+            *size_out = s.len();
+            *buf_out = s.as_ptr() as *const c_char;
+            true
+        }
+        Err(bad) => {
+            *bad_codepoint_out = bad;
+            false
+        }
+    }
+}
+
+/// Like `encode_loop_lossy`, but for UCS2 input: an adjacent high/low
+/// surrogate pair is recombined into the supplementary code point it
+/// represents (the same one-unit lookahead `encode_loop_wtf8` uses) before
+/// falling back to substituting U+FFFD for a genuinely lone surrogate,
+/// rather than substituting both units of a valid pair independently.
+#[inline(always)]
+fn encode_loop_ucs2_lossy<'a>(chain: &'a mut Vec<Segment>, buf: &[u16]) -> (&'a str, usize) {
+    let utf8_mult = 3;
+    let dest = get_dest(chain, utf8_mult * buf.len());
+    let last = dest.len();
+    let base = dest.as_mut_ptr();
+    let mut substitutions = 0usize;
+    let mut index = last;
+    unsafe {
+        let mut i = 0;
+        while i < buf.len() {
+            let cp = buf[i] as u32;
+            if is_high_surrogate(cp) {
+                if let Some(&next) = buf.get(i + 1) {
+                    let next_cp = next as u32;
+                    if is_low_surrogate(next_cp) {
+                        let combined = combine_surrogates(cp, next_cp);
+                        let mut codepoint_buf = [0; 4];
+                        let bytes = char::from_u32(combined)
+                            .unwrap()
+                            .encode_utf8(&mut codepoint_buf)
+                            .as_bytes();
+                        index = write_bytes_at(base, index, bytes);
+                        i += 2;
+                        continue;
+                    }
+                }
+                substitutions += 1;
+                index = write_bytes_at(base, index, &REPLACEMENT_UTF8);
+                i += 1;
+                continue;
+            }
+            if is_low_surrogate(cp) {
+                substitutions += 1;
+                index = write_bytes_at(base, index, &REPLACEMENT_UTF8);
+                i += 1;
+                continue;
+            }
+            let c = char::from_u32(cp).unwrap();
+            let mut codepoint_buf = [0; 4];
+            let bytes = c.encode_utf8(&mut codepoint_buf).as_bytes();
+            index = write_bytes_at(base, index, bytes);
+            i += 1;
+        }
+        dest.set_len(index);
+    }
+    (&dest.as_str()[last..], substitutions)
+}
+
+/// Convert a Py_UCS1 string to UTF-8. Never fails: every UCS1 code point is
+/// a valid Unicode scalar value. `substitutions_out` is always set to 0 and
+/// exists only for symmetry with `qdb_ucs2_to_utf8_lossy` and
+/// `qdb_ucs4_to_utf8_lossy`.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs1_to_utf8_lossy(
+    b: *mut qdb_pystr_buf,
+    count: usize,
+    input: *const u8,
+    size_out: *mut usize,
+    buf_out: *mut *const c_char,
+    substitutions_out: *mut usize,
+) {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    let utf8_mult = 2;
+    let (res, substitutions) = encode_loop_lossy(utf8_mult, &mut b.0, i, |c| Some(c as char));
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
+/// Convert a Py_UCS2 string to UTF-8, recombining an adjacent high/low
+/// surrogate pair into the astral scalar value it encodes (modeled on
+/// `char::decode_utf16`), and substituting U+FFFD for any other lone
+/// surrogate instead of failing the whole conversion. `substitutions_out`
+/// is set to the number of replacements made.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs2_to_utf8_lossy(
+    b: *mut qdb_pystr_buf,
+    count: usize,
+    input: *const u16,
+    size_out: *mut usize,
+    buf_out: *mut *const c_char,
+    substitutions_out: *mut usize,
+) {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    let (res, substitutions) = encode_loop_ucs2_lossy(&mut b.0, i);
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
+}
+
+/// Convert a Py_UCS4 string to UTF-8, substituting U+FFFD for any code
+/// point that cannot be encoded (e.g. a surrogate or a value >= 0x110000)
+/// instead of failing the whole conversion. `substitutions_out` is set to
+/// the number of replacements made.
+/// Returns a `buf_out` borrowed ptr of `size_out` len.
+/// The buffer is borrowed from `b`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs4_to_utf8_lossy(
+    b: *mut qdb_pystr_buf,
+    count: usize,
+    input: *const u32,
+    size_out: *mut usize,
+    buf_out: *mut *const c_char,
+    substitutions_out: *mut usize,
+) {
+    let b = &mut *b;
+    let i = from_raw_parts(input, count);
+
+    // Max 4 bytes allowed by RFC: https://www.rfc-editor.org/rfc/rfc3629#page-4
+    let utf8_mult = 4;
+    let (res, substitutions) = encode_loop_lossy(utf8_mult, &mut b.0, i, char::from_u32);
+    *size_out = res.len();
+    *buf_out = res.as_ptr() as *const c_char;
+    *substitutions_out = substitutions;
 }
 
 /// Convert a Py_UCS1 string to UTF-8.
@@ -267,6 +666,183 @@ pub unsafe extern "C" fn qdb_ucs4_to_utf8(
     }
 }
 
+/// The width, in bytes, of each code point written by `qdb_utf8_to_ucs`,
+/// mirroring CPython's flexible string representation: the narrowest of
+/// UCS1/UCS2/UCS4 that can hold every code point in the decoded string.
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum qdb_ucs_width {
+    UCS1 = 1,
+    UCS2 = 2,
+    UCS4 = 4,
+}
+
+/// A chain-of-buffers like `qdb_pystr_buf`, but holding decoded code points
+/// rather than encoded UTF-8 bytes. One sub-chain per output width is kept
+/// so that a pointer handed out for an earlier call, at any width, remains
+/// valid until the next `clear`/`truncate`.
+#[allow(non_camel_case_types)]
+pub struct qdb_ucs_buf {
+    ucs1: Vec<Vec<u8>>,
+    ucs2: Vec<Vec<u16>>,
+    ucs4: Vec<Vec<u32>>,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct qdb_ucs_pos {
+    pub ucs1: qdb_pystr_pos,
+    pub ucs2: qdb_pystr_pos,
+    pub ucs4: qdb_pystr_pos,
+}
+
+/// Prepare a new buffer. The buffer must be freed with `qdb_ucs_buf_free`.
+/// `qdb_utf8_to_ucs` will write to this buffer.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs_buf_new() -> *mut qdb_ucs_buf {
+    Box::into_raw(Box::new(qdb_ucs_buf {
+        ucs1: Vec::new(),
+        ucs2: Vec::new(),
+        ucs4: Vec::new(),
+    }))
+}
+
+fn tell_generic<T>(chain: &[Vec<T>]) -> qdb_pystr_pos {
+    let chain_pos = chain.len();
+    let elem_pos = if chain_pos > 0 { chain[chain_pos - 1].len() } else { 0 };
+    qdb_pystr_pos {
+        chain: chain_pos,
+        string: elem_pos,
+    }
+}
+
+/// Get current position. Use in conjunction with `truncate`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs_buf_tell(b: *const qdb_ucs_buf) -> qdb_ucs_pos {
+    let b = &*b;
+    qdb_ucs_pos {
+        ucs1: tell_generic(&b.ucs1),
+        ucs2: tell_generic(&b.ucs2),
+        ucs4: tell_generic(&b.ucs4),
+    }
+}
+
+fn truncate_generic<T>(chain: &mut Vec<Vec<T>>, pos: qdb_pystr_pos) {
+    chain.truncate(pos.chain);
+    if !chain.is_empty() {
+        chain[pos.chain - 1].truncate(pos.string);
+    }
+}
+
+/// Trim the buffer to the given position. Use in conjunction with `tell`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs_buf_truncate(b: *mut qdb_ucs_buf, pos: qdb_ucs_pos) {
+    let b = &mut *b;
+    truncate_generic(&mut b.ucs1, pos.ucs1);
+    truncate_generic(&mut b.ucs2, pos.ucs2);
+    truncate_generic(&mut b.ucs4, pos.ucs4);
+}
+
+fn clear_generic<T>(chain: &mut Vec<Vec<T>>) {
+    if !chain.is_empty() {
+        chain.truncate(1);
+        chain[0].clear();
+    }
+}
+
+/// Reset the converter's buffer to zero length.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs_buf_clear(b: *mut qdb_ucs_buf) {
+    let b = &mut *b;
+    clear_generic(&mut b.ucs1);
+    clear_generic(&mut b.ucs2);
+    clear_generic(&mut b.ucs4);
+}
+
+/// Free the buffer. Must be called after `qdb_ucs_buf_new`.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_ucs_buf_free(b: *mut qdb_ucs_buf) {
+    if !b.is_null() {
+        drop(Box::from_raw(b));
+    }
+}
+
+/// Same "stable address" trick as `get_dest`, genericized over the output
+/// element width so it can back any of the three sub-chains.
+fn get_dest_generic<T>(chain: &mut Vec<Vec<T>>, len: usize) -> &mut Vec<T> {
+    if !chain.is_empty() {
+        let last = chain.last_mut().unwrap();
+        if last.capacity() - last.len() >= len {
+            return chain.last_mut().unwrap();
+        }
+    }
+    chain.push(Vec::with_capacity(std::cmp::max(len, MIN_BUF_LEN)));
+    chain.last_mut().unwrap()
+}
+
+/// Decode a UTF-8 byte slice back into Python code points, picking the
+/// narrowest representation (UCS1, then UCS2, then UCS4) that can hold
+/// every code point in `input`, the same width-selection strategy CPython
+/// uses when building a `str` from decoded bytes.
+/// Returns a `buf_out` borrowed ptr of `count_out` code points of
+/// `width_out` bytes each. The buffer is borrowed from `b`.
+/// In case of malformed UTF-8, returns `false` and `error_offset_out` is
+/// set to the zero-based byte offset of the first invalid byte; the buffer
+/// is left untouched.
+#[no_mangle]
+pub unsafe extern "C" fn qdb_utf8_to_ucs(
+    b: *mut qdb_ucs_buf,
+    input: *const u8,
+    len: usize,
+    width_out: *mut qdb_ucs_width,
+    count_out: *mut usize,
+    buf_out: *mut *const c_void,
+    error_offset_out: *mut usize,
+) -> bool {
+    let b = &mut *b;
+    let bytes = from_raw_parts(input, len);
+    let s = match std::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => {
+            *error_offset_out = e.valid_up_to();
+            return false;
+        }
+    };
+
+    let mut max_cp = 0u32;
+    let mut count = 0usize;
+    for c in s.chars() {
+        max_cp = max_cp.max(c as u32);
+        count += 1;
+    }
+
+    if max_cp <= 0xff {
+        let dest = get_dest_generic(&mut b.ucs1, count);
+        let last = dest.len();
+        dest.extend(s.chars().map(|c| c as u32 as u8));
+        *width_out = qdb_ucs_width::UCS1;
+        *count_out = count;
+        *buf_out = dest[last..].as_ptr() as *const c_void;
+    } else if max_cp <= 0xffff {
+        let dest = get_dest_generic(&mut b.ucs2, count);
+        let last = dest.len();
+        dest.extend(s.chars().map(|c| c as u32 as u16));
+        *width_out = qdb_ucs_width::UCS2;
+        *count_out = count;
+        *buf_out = dest[last..].as_ptr() as *const c_void;
+    } else {
+        let dest = get_dest_generic(&mut b.ucs4, count);
+        let last = dest.len();
+        dest.extend(s.chars().map(|c| c as u32));
+        *width_out = qdb_ucs_width::UCS4;
+        *count_out = count;
+        *buf_out = dest[last..].as_ptr() as *const c_void;
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -282,11 +858,11 @@ mod tests {
             }
         }
 
-        fn chain(&self) -> &Vec<String> {
+        fn chain(&self) -> &Vec<Segment> {
             unsafe { &(*self.buf).0 }
         }
 
-        fn chain_mut(&mut self) -> &mut Vec<String> {
+        fn chain_mut(&mut self) -> &mut Vec<Segment> {
             unsafe { &mut (*self.buf).0 }
         }
 
@@ -363,6 +939,106 @@ mod tests {
                 Err(bad_codepoint)
             }
         }
+
+        // WTF-8 output may contain the raw 3-byte encoding of a lone
+        // surrogate, which is not valid UTF-8, so these return bytes
+        // rather than `&str`.
+
+        fn ucs2_to_wtf8(&mut self, input: &[u16]) -> Result<&'static [u8], u32> {
+            let mut size_out = 0;
+            let mut buf_out = std::ptr::null();
+            let mut bad_codepoint = 0u32;
+            let ok = unsafe {
+                qdb_ucs2_to_wtf8(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut bad_codepoint,
+                )
+            };
+            if ok {
+                Ok(unsafe { from_raw_parts(buf_out as *const u8, size_out) })
+            } else {
+                Err(bad_codepoint)
+            }
+        }
+
+        fn ucs4_to_wtf8(&mut self, input: &[u32]) -> Result<&'static [u8], u32> {
+            let mut size_out = 0;
+            let mut buf_out = std::ptr::null();
+            let mut bad_codepoint = 0u32;
+            let ok = unsafe {
+                qdb_ucs4_to_wtf8(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut bad_codepoint,
+                )
+            };
+            if ok {
+                Ok(unsafe { from_raw_parts(buf_out as *const u8, size_out) })
+            } else {
+                Err(bad_codepoint)
+            }
+        }
+
+        fn ucs1_to_utf8_lossy(&mut self, input: &[u8]) -> (&'static str, usize) {
+            let mut size_out = 0;
+            let mut buf_out = std::ptr::null();
+            let mut substitutions = 0usize;
+            unsafe {
+                qdb_ucs1_to_utf8_lossy(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut substitutions,
+                );
+            }
+            let slice = unsafe { from_raw_parts(buf_out as *const u8, size_out) };
+            (std::str::from_utf8(slice).unwrap(), substitutions)
+        }
+
+        fn ucs2_to_utf8_lossy(&mut self, input: &[u16]) -> (&'static str, usize) {
+            let mut size_out = 0;
+            let mut buf_out = std::ptr::null();
+            let mut substitutions = 0usize;
+            unsafe {
+                qdb_ucs2_to_utf8_lossy(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut substitutions,
+                );
+            }
+            let slice = unsafe { from_raw_parts(buf_out as *const u8, size_out) };
+            (std::str::from_utf8(slice).unwrap(), substitutions)
+        }
+
+        fn ucs4_to_utf8_lossy(&mut self, input: &[u32]) -> (&'static str, usize) {
+            let mut size_out = 0;
+            let mut buf_out = std::ptr::null();
+            let mut substitutions = 0usize;
+            unsafe {
+                qdb_ucs4_to_utf8_lossy(
+                    self.buf,
+                    input.len(),
+                    input.as_ptr(),
+                    &mut size_out,
+                    &mut buf_out,
+                    &mut substitutions,
+                );
+            }
+            let slice = unsafe { from_raw_parts(buf_out as *const u8, size_out) };
+            (std::str::from_utf8(slice).unwrap(), substitutions)
+        }
     }
 
     impl Drop for Buf {
@@ -373,6 +1049,99 @@ mod tests {
         }
     }
 
+    #[derive(Debug, PartialEq, Eq)]
+    enum DecodedUcs {
+        Ucs1(Vec<u8>),
+        Ucs2(Vec<u16>),
+        Ucs4(Vec<u32>),
+    }
+
+    struct UcsBuf {
+        buf: *mut qdb_ucs_buf,
+    }
+
+    impl UcsBuf {
+        fn new() -> Self {
+            Self {
+                buf: unsafe { qdb_ucs_buf_new() },
+            }
+        }
+
+        fn tell(&self) -> qdb_ucs_pos {
+            unsafe { qdb_ucs_buf_tell(self.buf) }
+        }
+
+        fn utf8_to_ucs(&mut self, input: &[u8]) -> Result<DecodedUcs, usize> {
+            let mut width_out = qdb_ucs_width::UCS1;
+            let mut count_out = 0usize;
+            let mut buf_out = std::ptr::null();
+            let mut error_offset = 0usize;
+            let ok = unsafe {
+                qdb_utf8_to_ucs(
+                    self.buf,
+                    input.as_ptr(),
+                    input.len(),
+                    &mut width_out,
+                    &mut count_out,
+                    &mut buf_out,
+                    &mut error_offset,
+                )
+            };
+            if !ok {
+                return Err(error_offset);
+            }
+            Ok(match width_out {
+                qdb_ucs_width::UCS1 => {
+                    DecodedUcs::Ucs1(unsafe { from_raw_parts(buf_out as *const u8, count_out) }.to_vec())
+                }
+                qdb_ucs_width::UCS2 => {
+                    DecodedUcs::Ucs2(unsafe { from_raw_parts(buf_out as *const u16, count_out) }.to_vec())
+                }
+                qdb_ucs_width::UCS4 => {
+                    DecodedUcs::Ucs4(unsafe { from_raw_parts(buf_out as *const u32, count_out) }.to_vec())
+                }
+            })
+        }
+    }
+
+    impl Drop for UcsBuf {
+        fn drop(&mut self) {
+            unsafe {
+                qdb_ucs_buf_free(self.buf);
+            }
+        }
+    }
+
+    #[test]
+    fn test_utf8_to_ucs_picks_ucs1_for_latin1_range() {
+        let mut b = UcsBuf::new();
+        let decoded = b.utf8_to_ucs("a\u{b5}".as_bytes()).unwrap();
+        assert_eq!(decoded, DecodedUcs::Ucs1(vec![0x61, 0xb5]));
+    }
+
+    #[test]
+    fn test_utf8_to_ucs_picks_ucs2() {
+        let mut b = UcsBuf::new();
+        let decoded = b.utf8_to_ucs("a\u{569c}".as_bytes()).unwrap();
+        assert_eq!(decoded, DecodedUcs::Ucs2(vec![0x61, 0x569c]));
+    }
+
+    #[test]
+    fn test_utf8_to_ucs_picks_ucs4() {
+        let mut b = UcsBuf::new();
+        let decoded = b.utf8_to_ucs("a\u{1f4a9}".as_bytes()).unwrap();
+        assert_eq!(decoded, DecodedUcs::Ucs4(vec![0x61, 0x1f4a9]));
+    }
+
+    #[test]
+    fn test_utf8_to_ucs_reports_malformed_utf8_offset() {
+        let mut b = UcsBuf::new();
+        let before_pos = b.tell();
+        let err = b.utf8_to_ucs(b"ab\xffcd").unwrap_err();
+        assert_eq!(err, 2);
+        assert_eq!(b.tell(), before_pos);
+    }
+
     #[test]
     fn test_empty() {
         let b = Buf::new();
@@ -389,12 +1158,12 @@ mod tests {
         assert_eq!(s1, "hello");
         assert_eq!(b.chain_mut().len(), 1);
         assert_eq!(b.chain_mut()[0].as_str().as_ptr(), s1.as_ptr());
-        assert_eq!(b.chain()[0], "hello");
+        assert_eq!(b.chain()[0].as_str(), "hello");
         assert_eq!(b.tell().chain, 1);
         assert_eq!(b.tell().string, 5);
         b.clear();
         assert_eq!(b.chain().len(), 1);
-        assert_eq!(b.chain()[0], "");
+        assert_eq!(b.chain()[0].as_str(), "");
         let s2 = b.ucs1_to_utf8(b"");
         assert_eq!(s2, "");
         assert_eq!(
@@ -470,6 +1239,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_small_strings_use_small_segment() {
+        let mut b = Buf::new();
+
+        // A short tag value should land in a `Segment::Small`, not force a
+        // full `MIN_BUF_LEN` allocation.
+        let s1 = b.ucs1_to_utf8(b"tag1");
+        assert_eq!(s1, "tag1");
+        assert_eq!(b.chain_mut()[0].capacity(), SMALL_BUF_LEN);
+
+        // Appending a string too big to fit the rest of the small segment
+        // spills into a new `Segment::Large`, without moving the first
+        // segment's storage: the earlier pointer must stay valid.
+        let big_string = "hello world".repeat(1000);
+        assert!(big_string.len() > MIN_BUF_LEN);
+        let s2 = b.ucs1_to_utf8(big_string.as_bytes());
+        assert_eq!(s2, big_string);
+        assert_eq!(b.chain_mut().len(), 2);
+        assert!(b.chain_mut()[1].capacity() >= MIN_BUF_LEN);
+        assert_eq!(s1.as_ptr(), b.chain()[0].as_str().as_ptr());
+        assert_eq!(s1, "tag1");
+    }
+
     #[test]
     fn test_ucs2() {
         let mut b = Buf::new();
@@ -612,4 +1404,91 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_ucs2_to_wtf8_combines_surrogate_pair() {
+        let mut b = Buf::new();
+
+        // U+1F4A9 PILE OF POO, as a UTF-16 surrogate pair.
+        let s1 = b.ucs2_to_wtf8(&[0xd83d, 0xdca9]).unwrap();
+        assert_eq!(s1, "\u{1f4a9}".as_bytes());
+    }
+
+    #[test]
+    fn test_ucs2_to_wtf8_preserves_lone_surrogates() {
+        let mut b = Buf::new();
+
+        // A high surrogate with no following low surrogate, a low
+        // surrogate with no preceding high surrogate, and a high
+        // surrogate as the very last element of the input.
+        let s1 = b.ucs2_to_wtf8(&[0x61, 0xd800, 0x62]).unwrap();
+        assert_eq!(s1, &[0x61, 0xed, 0xa0, 0x80, 0x62]);
+
+        let s2 = b.ucs2_to_wtf8(&[0x61, 0xdc00, 0x62]).unwrap();
+        assert_eq!(s2, &[0x61, 0xed, 0xb0, 0x80, 0x62]);
+
+        let s3 = b.ucs2_to_wtf8(&[0x61, 0xd800]).unwrap();
+        assert_eq!(s3, &[0x61, 0xed, 0xa0, 0x80]);
+    }
+
+    #[test]
+    fn test_ucs4_to_wtf8_combines_surrogate_pair_and_rejects_out_of_range() {
+        let mut b = Buf::new();
+
+        let s1 = b.ucs4_to_wtf8(&[0xd83d, 0xdca9]).unwrap();
+        assert_eq!(s1, "\u{1f4a9}".as_bytes());
+
+        let before_pos = b.tell();
+        let s2 = b.ucs4_to_wtf8(&[0x61, 0x110000]);
+        assert!(s2.is_err());
+        assert_eq!(s2.unwrap_err(), 0x110000);
+        assert_eq!(b.tell(), before_pos);
+    }
+
+    #[test]
+    fn test_ucs1_to_utf8_lossy_never_substitutes() {
+        let mut b = Buf::new();
+        let (s, substitutions) = b.ucs1_to_utf8_lossy(b"hello");
+        assert_eq!(s, "hello");
+        assert_eq!(substitutions, 0);
+    }
+
+    #[test]
+    fn test_ucs2_to_utf8_lossy_substitutes_surrogates() {
+        let mut b = Buf::new();
+        let (s, substitutions) = b.ucs2_to_utf8_lossy(&[0x61, 0xd800, 0x62]);
+        assert_eq!(s, "a\u{fffd}b");
+        assert_eq!(substitutions, 1);
+
+        // Confirm the conversion runs to completion rather than stopping at
+        // the first bad code point.
+        let (s2, substitutions2) = b.ucs2_to_utf8_lossy(&[0xd800, 0x63, 0xdc00]);
+        assert_eq!(s2, "\u{fffd}c\u{fffd}");
+        assert_eq!(substitutions2, 2);
+    }
+
+    #[test]
+    fn test_ucs2_to_utf8_lossy_combines_surrogate_pairs() {
+        let mut b = Buf::new();
+
+        // A genuine surrogate pair must be recombined into its single
+        // astral scalar value, not substituted as two independent U+FFFDs.
+        let (s, substitutions) = b.ucs2_to_utf8_lossy(&[0xd83d, 0xdca9]);
+        assert_eq!(s, "\u{1f4a9}");
+        assert_eq!(substitutions, 0);
+
+        // A pair surrounded by ordinary characters still combines, and any
+        // other lone surrogate in the same input is still substituted.
+        let (s2, substitutions2) = b.ucs2_to_utf8_lossy(&[0x61, 0xd83d, 0xdca9, 0xd800, 0x62]);
+        assert_eq!(s2, "a\u{1f4a9}\u{fffd}b");
+        assert_eq!(substitutions2, 1);
+    }
+
+    #[test]
+    fn test_ucs4_to_utf8_lossy_substitutes_surrogates_and_out_of_range() {
+        let mut b = Buf::new();
+        let (s, substitutions) = b.ucs4_to_utf8_lossy(&[0x61, 0xd800, 0x110000, 0x62]);
+        assert_eq!(s, "a\u{fffd}\u{fffd}b");
+        assert_eq!(substitutions, 2);
+    }
 }